@@ -0,0 +1,238 @@
+use crate::decoder::DecodeState;
+use crate::encoder::EncodeState;
+use crate::{
+    CompressionLevel, HeaderFormat, LzariError, LzariParams, MatchFinder, ParseStrategy, Token,
+    Tokens, Trace,
+};
+
+/// Owned counterpart of [`LZARIContext`](crate::LZARIContext) for compressing many small
+/// inputs. The window, match tree and model tables are allocated on first use and reused by
+/// every later call with the same parameters.
+#[derive(Debug)]
+pub struct Codec {
+    encoder: EncodeState,
+    decoder: DecodeState,
+}
+
+impl Codec {
+    pub fn new() -> Self {
+        Self {
+            encoder: EncodeState::new(),
+            decoder: DecodeState::new(),
+        }
+    }
+
+    pub fn header(mut self, header: HeaderFormat) -> Self {
+        self.encoder.header = header;
+        self.decoder.header = header;
+        self
+    }
+
+    pub fn params(mut self, params: LzariParams) -> Self {
+        self.encoder.params = params.clone();
+        self.decoder.params = params;
+        self
+    }
+
+    pub fn level(mut self, level: CompressionLevel) -> Self {
+        self.encoder.finder = level.match_finder();
+        self.encoder.parsing = level.parsing();
+        self
+    }
+
+    /// Replaces the match finder picked by the compression level.
+    pub fn match_finder(mut self, finder: Box<dyn MatchFinder>) -> Self {
+        self.encoder.finder = finder;
+        self
+    }
+
+    /// Replaces the parse strategy picked by the compression level.
+    pub fn parsing(mut self, parsing: ParseStrategy) -> Self {
+        self.encoder.parsing = parsing;
+        self
+    }
+
+    /// Emits exactly what Okumura's LZARI.C emits on LP64 systems, as
+    /// [`Encoder::reference`](crate::Encoder::reference) does, and decodes such streams.
+    pub fn reference(mut self) -> Self {
+        self.encoder.reference();
+        self.decoder.header = self.encoder.header;
+        self.decoder.params = self.encoder.params.clone();
+        self.decoder.reference = true;
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.decoder.strict = strict;
+        self
+    }
+
+    /// Compresses `input` and appends the stream to `out`.
+    pub fn encode_into(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<(), LzariError> {
+        self.encoder.reset();
+        self.encoder.size = Some(input.len() as u64);
+
+        // Let the encoder write straight into `out` rather than copying its buffer over.
+        std::mem::swap(&mut self.encoder.outbuf, out);
+        let rv = self
+            .encoder
+            .encode_bytes(input)
+            .and_then(|_| self.encoder.finish());
+        std::mem::swap(&mut self.encoder.outbuf, out);
+        rv
+    }
+
+    /// The parse the encoder settles on for `input`.
+    pub fn tokenize(&mut self, input: &[u8]) -> Vec<Token> {
+        // Without a header to write, encoding cannot fail.
+        let header = std::mem::replace(&mut self.encoder.header, HeaderFormat::Headerless);
+        self.encoder.reset();
+        self.encoder.size = None;
+        self.encoder.tokens = Some(vec![]);
+        self.encoder
+            .encode_bytes(input)
+            .and_then(|_| self.encoder.finish())
+            .expect("headerless encoding failed");
+        self.encoder.header = header;
+        self.encoder.outbuf.clear();
+        self.encoder.tokens.take().unwrap_or_default()
+    }
+
+    /// Compresses the data `tokens` stand for, coding exactly that parse, and appends the
+    /// stream to `out`. Fails if a match is out of range for the parameters.
+    pub fn encode_tokens(&mut self, tokens: &[Token], out: &mut Vec<u8>) -> Result<(), LzariError> {
+        self.encoder.reset();
+        std::mem::swap(&mut self.encoder.outbuf, out);
+        let rv = self.encoder.encode_tokens(tokens);
+        std::mem::swap(&mut self.encoder.outbuf, out);
+        rv
+    }
+
+    /// Iterates over the tokens of the stream at the start of `input`.
+    pub fn decode_tokens<'a>(&'a mut self, input: &'a [u8]) -> Tokens<'a> {
+        Tokens(self.trace_tokens(input))
+    }
+
+    /// Like `decode_tokens`, for headerless streams whose size is stored elsewhere.
+    pub fn decode_sized_tokens<'a>(&'a mut self, input: &'a [u8], size: u64) -> Tokens<'a> {
+        Tokens(self.trace_sized_tokens(input, size))
+    }
+
+    /// Like `decode_tokens`, along with where each token lies in the stream and what it cost.
+    pub fn trace_tokens<'a>(&'a mut self, input: &'a [u8]) -> Trace<'a> {
+        self.trace(input, None)
+    }
+
+    /// Like `trace_tokens`, for headerless streams whose size is stored elsewhere.
+    pub fn trace_sized_tokens<'a>(&'a mut self, input: &'a [u8], size: u64) -> Trace<'a> {
+        self.trace(input, Some(size))
+    }
+
+    fn trace<'a>(&'a mut self, input: &'a [u8], size: Option<u64>) -> Trace<'a> {
+        self.decoder.size = size;
+        Trace::new(&mut self.decoder, input)
+    }
+
+    /// Decompresses the stream at the start of `input`, appends the data to `out` and returns
+    /// the length of the stream.
+    pub fn decode_into(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<usize, LzariError> {
+        self.decode(input, None, out)
+    }
+
+    /// Like `decode_into`, for headerless streams whose size is stored elsewhere.
+    pub fn decode_sized_into(
+        &mut self,
+        input: &[u8],
+        size: u64,
+        out: &mut Vec<u8>,
+    ) -> Result<usize, LzariError> {
+        self.decode(input, Some(size), out)
+    }
+
+    fn decode(
+        &mut self,
+        input: &[u8],
+        size: Option<u64>,
+        out: &mut Vec<u8>,
+    ) -> Result<usize, LzariError> {
+        self.decoder.size = size;
+        self.decoder.decode_slice(input, out)
+    }
+}
+
+impl Default for Codec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_input_after_long() {
+        // The match finder looks past the end of a short input, where the window must not
+        // still hold the last one.
+        let long = b"ba".repeat(3000);
+        let short = b"aQaQaaQa";
+        let mut codec = Codec::new();
+        let mut stream = vec![];
+        codec.encode_into(&long, &mut stream).unwrap();
+        let mut out = vec![];
+        codec.decode_into(&stream, &mut out).unwrap();
+
+        stream.clear();
+        codec.encode_into(short, &mut stream).unwrap();
+        let mut fresh = vec![];
+        Codec::new().encode_into(short, &mut fresh).unwrap();
+        assert_eq!(stream, fresh);
+
+        out.clear();
+        codec.decode_into(&stream, &mut out).unwrap();
+        assert_eq!(out, short);
+    }
+
+    #[test]
+    fn trace_symbols() {
+        // The fresh model ranks character `c` as symbol `c + 1`; the first update moves it to
+        // the front.
+        let mut codec = Codec::new();
+        let mut stream = vec![];
+        codec.encode_into(b"aa", &mut stream).unwrap();
+        let traces: Vec<_> = codec.trace_tokens(&stream).map(Result::unwrap).collect();
+        assert_eq!((traces[0].symbol, traces[0].character), (98, 97));
+        assert_eq!((traces[1].symbol, traces[1].character), (1, 97));
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        let data = b"It was the best of times, it was the worst of times, it was the age of \
+            wisdom, it was the age of foolishness, it was the epoch of belief";
+        for level in [
+            CompressionLevel::Fast,
+            CompressionLevel::Default,
+            CompressionLevel::Best,
+        ] {
+            let mut codec = Codec::new().level(level);
+            let tokens = codec.tokenize(data);
+            let mut stream = vec![];
+            codec.encode_into(data, &mut stream).unwrap();
+            let mut reencoded = vec![];
+            codec.encode_tokens(&tokens, &mut reencoded).unwrap();
+            assert_eq!(reencoded, stream, "{level:?}");
+
+            let decoded: Vec<_> = codec.decode_tokens(&stream).map(Result::unwrap).collect();
+            assert_eq!(decoded, tokens, "{level:?}");
+
+            let mut codec = Codec::new().level(level).header(HeaderFormat::Headerless);
+            stream.clear();
+            codec.encode_into(data, &mut stream).unwrap();
+            let decoded: Vec<_> = codec
+                .decode_sized_tokens(&stream, data.len() as u64)
+                .map(Result::unwrap)
+                .collect();
+            assert_eq!(decoded, tokens, "{level:?}");
+        }
+    }
+}
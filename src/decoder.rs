@@ -0,0 +1,660 @@
+use std::io::{self, Read};
+use std::slice;
+
+use crate::model::Model;
+use crate::push::SliceInput;
+use crate::{HeaderFormat, LzariError, LzariParams, Match, Token, TokenTrace};
+
+pub(crate) enum Fetch {
+    Byte(u8),
+    End,
+    Pending,
+}
+
+pub(crate) trait Input {
+    fn fetch(&mut self) -> Result<Fetch, LzariError>;
+}
+
+struct ReadInput<'a, R>(&'a mut R);
+
+impl<R: Read> Input for ReadInput<'_, R> {
+    fn fetch(&mut self) -> Result<Fetch, LzariError> {
+        let mut byte = 0;
+        loop {
+            match self.0.read(slice::from_mut(&mut byte)) {
+                Ok(0) => return Ok(Fetch::End),
+                Ok(_) => return Ok(Fetch::Byte(byte)),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+// Where the decoder is within the stream. Renormalisation pulls one bit at a time, so the
+// decoder can stop between any two bits and pick up from here once more input arrives.
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Header,
+    Start(usize),
+    Char,
+    CharRenorm(usize),
+    Position(usize),
+    PositionRenorm(usize, usize),
+    Copy,
+}
+
+#[derive(Debug)]
+pub(crate) struct DecodeState {
+    pub(crate) header: HeaderFormat,
+    pub(crate) params: LzariParams,
+    pub(crate) strict: bool,
+    // LZARI.C writes nothing after the header of an empty stream.
+    pub(crate) reference: bool,
+    pub(crate) size: Option<u64>,
+    // Where to collect the tokens as they are decoded, for tools that look at the parse.
+    pub(crate) tokens: Option<Vec<TokenTrace>>,
+    // The token being decoded, while it is traced.
+    trace: TokenTrace,
+    step: Step,
+
+    header_buf: Vec<u8>,
+    header_len: usize,
+    in_buffer: u8,
+    in_mask: u8,
+    // Bytes fetched after the header, including the padding made up past the end.
+    in_cursor: u64,
+    in_len: Option<u64>,
+    padding_bits: usize,
+    need_bit: bool,
+
+    text_buf: Vec<u8>,
+    r: usize,
+    textsize: u64,
+    count: u64,
+    copy_pos: usize,
+    copy_len: usize,
+
+    // Arithmetic Compression
+    low: usize,
+    high: usize,
+    value: usize,
+    model: Model,
+}
+
+impl DecodeState {
+    pub(crate) fn new() -> Self {
+        Self {
+            header: HeaderFormat::default(),
+            params: LzariParams::default(),
+            strict: false,
+            reference: false,
+            size: None,
+            tokens: None,
+            trace: TokenTrace {
+                token: Token::Literal(0),
+                bit_offset: 0,
+                offset: 0,
+                symbol: 0,
+                character: 0,
+                freq: 0,
+                total: 0,
+                bits: 0.0,
+            },
+            step: Step::Header,
+            header_buf: vec![],
+            header_len: 0,
+            in_buffer: 0,
+            in_mask: 0,
+            in_cursor: 0,
+            in_len: None,
+            padding_bits: 0,
+            need_bit: false,
+            text_buf: vec![],
+            r: 0,
+            textsize: 0,
+            count: 0,
+            copy_pos: 0,
+            copy_len: 0,
+            low: 0,
+            high: 0,
+            value: 0,
+            model: Model::new(),
+        }
+    }
+
+    // Gets ready for another stream, keeping the window and model tables that were allocated
+    // for the last one.
+    pub(crate) fn reset(&mut self) {
+        self.step = Step::Header;
+        if let Some(tokens) = &mut self.tokens {
+            tokens.clear();
+        }
+        self.header_buf.clear();
+        self.header_len = 0;
+        self.in_buffer = 0;
+        self.in_mask = 0;
+        self.in_cursor = 0;
+        self.in_len = None;
+        self.padding_bits = 0;
+        self.need_bit = false;
+        self.textsize = 0;
+        self.count = 0;
+        self.copy_pos = 0;
+        self.copy_len = 0;
+        self.low = 0;
+        self.value = 0;
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        matches!(self.step, Step::Char) && self.count == self.textsize
+    }
+
+    pub(crate) fn consumed(&self) -> u64 {
+        let available = self.header_len as u64 + self.in_len.unwrap_or(self.in_cursor);
+        self.stream_len().min(available)
+    }
+
+    // Length of the stream the encoder wrote, as opposed to how far the decoder had to read
+    // ahead. Every renormalisation step retires one bit on both sides, and `encode_end` always
+    // settles the pending underflow bits with two more, rounded up to a whole byte.
+    fn stream_len(&self) -> u64 {
+        if self.in_cursor == 0 {
+            return self.header_len as u64;
+        }
+        self.header_len as u64 + (self.bits_retired() + 2).div_ceil(8)
+    }
+
+    // Bits the decoder has shifted out of `value` since the start of the body.
+    fn bits_retired(&self) -> u64 {
+        let bits_read = self.in_cursor * 8 - u64::from(self.in_mask.trailing_zeros());
+        bits_read - (u64::from(self.params.precision()) + 2)
+    }
+
+    // Tracing is kept out of line, clear of the decoding loop.
+    #[cold]
+    fn start_trace(&mut self, sym: usize) {
+        let freq = self.model.sym_freq[sym];
+        let total = self.model.sym_cum(0) as u32;
+        self.trace = TokenTrace {
+            token: Token::Literal(0),
+            bit_offset: self.header_len as u64 * 8 + self.bits_retired(),
+            offset: self.count,
+            symbol: sym,
+            character: self.model.sym_to_char[sym].into(),
+            freq,
+            total,
+            bits: (f64::from(total) / f64::from(freq)).log2(),
+        };
+    }
+
+    #[cold]
+    fn end_trace(&mut self, token: Token) {
+        if let Some(tokens) = &mut self.tokens {
+            tokens.push(TokenTrace {
+                token,
+                ..self.trace
+            });
+        }
+    }
+
+    fn read_header(&mut self, input: &mut impl Input) -> Result<bool, LzariError> {
+        loop {
+            match self.parse_header() {
+                Err(LzariError::HeaderTooShort) => match input.fetch()? {
+                    Fetch::Byte(byte) => self.header_buf.push(byte),
+                    Fetch::End => return Err(LzariError::HeaderTooShort),
+                    Fetch::Pending => return Ok(false),
+                },
+                rv => {
+                    (self.textsize, self.header_len) = rv?;
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    // The header proper, followed by the dictionary identifier if one is expected.
+    fn parse_header(&self) -> Result<(u64, usize), LzariError> {
+        let (size, len) = self.header.read(&self.header_buf, self.size)?;
+        let Some(id) = self
+            .params
+            .dictionary_id()
+            .filter(|_| self.header.is_framed())
+        else {
+            return Ok((size, len));
+        };
+        let stored = self
+            .header_buf
+            .get(len..len + 4)
+            .ok_or(LzariError::HeaderTooShort)?;
+        if stored != id.to_le_bytes() {
+            return Err(LzariError::DictionaryMismatch);
+        }
+        Ok((size, len + 4))
+    }
+
+    fn get_bit(&mut self, input: &mut impl Input) -> Result<Option<bool>, LzariError> {
+        if self.in_mask <= 1 {
+            self.in_buffer = match self.in_len {
+                Some(_) => 0xFF,
+                None => match input.fetch()? {
+                    Fetch::Byte(byte) => byte,
+                    Fetch::End => {
+                        self.in_len = Some(self.in_cursor);
+                        0xFF
+                    }
+                    Fetch::Pending => return Ok(None),
+                },
+            };
+            self.in_cursor += 1;
+            self.in_mask = 128;
+        } else {
+            self.in_mask >>= 1;
+        }
+        if self.in_len.is_some() {
+            // Reading past the end is normal while the decoder fills its lookahead, but once
+            // every bit of `value` has been made up the stream is definitely cut short.
+            // A conforming encoder never leaves more than M bits unwritten.
+            self.padding_bits += 1;
+            let precision = self.params.precision() as usize;
+            let max_padding = if self.strict {
+                precision
+            } else {
+                precision + 2
+            };
+            if self.padding_bits > max_padding {
+                return Err(LzariError::Truncated);
+            }
+        }
+        Ok(Some(self.in_buffer & self.in_mask != 0))
+    }
+
+    fn start_model(&mut self) {
+        let ring_buf_size = self.params.ring_buf_size();
+        self.model.start(&self.params);
+        self.text_buf.clear();
+        self.text_buf.resize(ring_buf_size, 0);
+        self.r = ring_buf_size - self.params.max_match_len();
+        self.params.init_window(&mut self.text_buf, self.r);
+    }
+
+    // Narrows the interval to the symbol under `value`; `renormalize` has to run before the
+    // next symbol can be decoded.
+    fn decode_char(&mut self) -> Result<usize, LzariError> {
+        let range = self.high - self.low;
+        let offset = self
+            .value
+            .checked_sub(self.low)
+            .filter(|&offset| offset < range)
+            .ok_or(LzariError::InvalidSymbol)?;
+        let total = self.model.sym_cum(0);
+        let sym = self
+            .model
+            .binary_search_sym((((offset + 1) * total) - 1) / range);
+        self.high = self.low + ((range * self.model.sym_cum(sym - 1)) / total);
+        self.low += (range * self.model.sym_cum(sym)) / total;
+        Ok(sym)
+    }
+
+    fn decode_position(&mut self) -> Result<usize, LzariError> {
+        let range = self.high - self.low;
+        let offset = self
+            .value
+            .checked_sub(self.low)
+            .filter(|&offset| offset < range)
+            .ok_or(LzariError::InvalidPosition)?;
+        let total = self.model.position_cum[0] as usize;
+        let position = self
+            .model
+            .binary_search_pos((((offset + 1) * total) - 1) / range);
+        self.high = self.low + ((range * self.model.position_cum[position] as usize) / total);
+        self.low += (range * self.model.position_cum[position + 1] as usize) / total;
+        Ok(position)
+    }
+
+    // Returns `Ok(false)` if the input ran dry; the next call carries on with the same bit.
+    fn renormalize(&mut self, input: &mut impl Input) -> Result<bool, LzariError> {
+        loop {
+            if self.need_bit {
+                let Some(bit) = self.get_bit(input)? else {
+                    return Ok(false);
+                };
+                self.value = (self.value << 1) + usize::from(bit);
+                self.need_bit = false;
+            }
+            let (q1, q2, q3) = (self.params.q1(), self.params.q2(), self.params.q3());
+            if self.low >= q2 {
+                self.value -= q2;
+                self.low -= q2;
+                self.high -= q2;
+            } else if self.low >= q1 && self.high <= q3 {
+                self.value -= q1;
+                self.low -= q1;
+                self.high -= q1;
+            } else if self.high > q2 {
+                return Ok(true);
+            }
+            self.low += self.low;
+            self.high += self.high;
+            self.need_bit = true;
+        }
+    }
+
+    fn put_byte(&mut self, c: u8) {
+        self.text_buf[self.r] = c;
+        self.r = (self.r + 1) & (self.params.ring_buf_size() - 1);
+        self.count += 1;
+    }
+
+    // Decodes the whole of `stream`, returning the data and the number of bytes the stream
+    // took up.
+    pub(crate) fn decode_slice(
+        &mut self,
+        stream: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<usize, LzariError> {
+        self.reset();
+        let mut input = SliceInput {
+            data: stream,
+            end: true,
+        };
+        let mut chunk = [0; 4096];
+        loop {
+            let n = self.fill(&mut input, &mut chunk)?;
+            if n == 0 {
+                return Ok(self.consumed() as usize);
+            }
+            out.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    // Decodes into `buf` until it is full, the stream ends, or `input` has nothing more to give
+    // for now.
+    pub(crate) fn fill(
+        &mut self,
+        input: &mut impl Input,
+        buf: &mut [u8],
+    ) -> Result<usize, LzariError> {
+        let mut n = 0;
+        while n < buf.len() {
+            match self.step {
+                Step::Header => {
+                    if !self.read_header(input)? {
+                        break;
+                    }
+                    self.high = self.params.q4();
+                    self.step = if self.reference && self.textsize == 0 {
+                        Step::Start(0)
+                    } else {
+                        Step::Start(self.params.precision() as usize + 2)
+                    };
+                }
+                Step::Start(0) => {
+                    self.start_model();
+                    self.step = Step::Char;
+                }
+                Step::Start(bits) => {
+                    let Some(bit) = self.get_bit(input)? else {
+                        break;
+                    };
+                    self.value = (self.value << 1) + usize::from(bit);
+                    self.step = Step::Start(bits - 1);
+                }
+                Step::Char => {
+                    if self.count == self.textsize {
+                        break;
+                    }
+                    let sym = self.decode_char()?;
+                    if self.tokens.is_some() {
+                        self.start_trace(sym);
+                    }
+                    self.step = Step::CharRenorm(sym);
+                }
+                Step::CharRenorm(sym) => {
+                    if !self.renormalize(input)? {
+                        break;
+                    }
+                    let c = usize::from(self.model.sym_to_char[sym]);
+                    self.model.update(sym);
+                    if c < 256 {
+                        if self.tokens.is_some() {
+                            self.end_trace(Token::Literal(c as u8));
+                        }
+                        self.put_byte(c as u8);
+                        buf[n] = c as u8;
+                        n += 1;
+                        self.step = Step::Char;
+                    } else {
+                        let j = c - 255 + self.params.threshold();
+                        if j as u64 > self.textsize - self.count {
+                            return Err(LzariError::SizeOverrun);
+                        }
+                        self.step = Step::Position(j);
+                    }
+                }
+                Step::Position(j) => {
+                    let position = self.decode_position()?;
+                    self.step = Step::PositionRenorm(j, position);
+                }
+                Step::PositionRenorm(j, position) => {
+                    if !self.renormalize(input)? {
+                        break;
+                    }
+                    self.copy_pos =
+                        (self.r.wrapping_sub(position + 1)) & (self.params.ring_buf_size() - 1);
+                    self.copy_len = j;
+                    if self.tokens.is_some() {
+                        self.trace.bits += f64::from(self.model.position_cost(position));
+                        self.end_trace(Token::Match(Match {
+                            distance: position + 1,
+                            length: j,
+                        }));
+                    }
+                    self.step = Step::Copy;
+                }
+                Step::Copy => {
+                    let c = self.text_buf[self.copy_pos];
+                    self.copy_pos = (self.copy_pos + 1) & (self.params.ring_buf_size() - 1);
+                    self.copy_len -= 1;
+                    self.put_byte(c);
+                    buf[n] = c;
+                    n += 1;
+                    if self.copy_len == 0 {
+                        self.step = Step::Char;
+                    }
+                }
+            }
+        }
+
+        if self.is_done()
+            && self.strict
+            && self
+                .in_len
+                .is_some_and(|len| self.stream_len() > self.header_len as u64 + len)
+        {
+            return Err(LzariError::Truncated);
+        }
+        Ok(n)
+    }
+}
+
+#[derive(Debug)]
+pub struct Decoder<R> {
+    reader: R,
+    state: DecodeState,
+}
+
+impl<R: Read> Decoder<R> {
+    /// The decoder asks `reader` for one byte at a time, so wrap an unbuffered source such as
+    /// a `File` or socket in a [`BufReader`](std::io::BufReader). To fill its code value it
+    /// reads up to ⌈(precision + 2) / 8⌉ bytes past the end of the stream; if other data
+    /// follows, seek `reader` back to [`consumed`](Self::consumed) before reading it.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            state: DecodeState::new(),
+        }
+    }
+
+    pub fn header(mut self, header: HeaderFormat) -> Self {
+        self.state.header = header;
+        self
+    }
+
+    pub fn params(mut self, params: LzariParams) -> Self {
+        self.state.params = params;
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.state.strict = strict;
+        self
+    }
+
+    /// Reads the streams Okumura's LZARI.C writes on LP64 systems. See
+    /// [`Encoder::reference`](crate::Encoder::reference).
+    pub fn reference(mut self) -> Self {
+        self.state.header = HeaderFormat::U64Le;
+        self.state.params = LzariParams::default();
+        self.state.reference = true;
+        self
+    }
+
+    pub fn size(mut self, size: u64) -> Self {
+        self.state.size = Some(size);
+        self
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Number of input bytes that belong to the stream, not counting anything the decoder had
+    /// to read ahead past its end.
+    pub fn consumed(&self) -> u64 {
+        self.state.consumed()
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) -> Result<usize, LzariError> {
+        self.state.fill(&mut ReadInput(&mut self.reader), buf)
+    }
+
+    pub(crate) fn decode_to_end(&mut self, out: &mut Vec<u8>) -> Result<(), LzariError> {
+        let mut chunk = [0; 4096];
+        loop {
+            let n = self.fill(&mut chunk)?;
+            if n == 0 {
+                return Ok(());
+            }
+            out.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(self.fill(buf)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LZARIContext;
+
+    const TEXT: &[u8] = b"the quick brown fox jumps over the lazy dog, the quick brown fox";
+
+    #[test]
+    fn header_too_short() {
+        let stream = LZARIContext::new(TEXT).encode().unwrap();
+        for len in 0..4 {
+            let rv = LZARIContext::new(&stream[..len]).decode();
+            assert!(
+                matches!(rv, Err(LzariError::HeaderTooShort)),
+                "{len}: {rv:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let stream = LZARIContext::new(TEXT).encode().unwrap();
+        for len in [5, stream.len() / 2, stream.len() - 1] {
+            let rv = LZARIContext::new(&stream[..len]).strict(true).decode();
+            assert!(matches!(rv, Err(LzariError::Truncated)), "{len}: {rv:?}");
+        }
+    }
+
+    #[test]
+    fn huge_size_on_short_body() {
+        let mut stream = u32::MAX.to_le_bytes().to_vec();
+        stream.extend(b"0123456789");
+        let rv = LZARIContext::new(&stream).decode();
+        assert!(matches!(rv, Err(LzariError::Truncated)), "{rv:?}");
+    }
+
+    #[test]
+    fn size_overrun() {
+        // Three literals and a match that runs past a size of 5.
+        let mut stream = LZARIContext::new(b"abcabcabcabc").encode().unwrap();
+        stream[..4].copy_from_slice(&5u32.to_le_bytes());
+        let rv = LZARIContext::new(&stream).decode();
+        assert!(matches!(rv, Err(LzariError::SizeOverrun)), "{rv:?}");
+    }
+
+    #[test]
+    fn flipped_bits() {
+        let stream = LZARIContext::new(TEXT).encode().unwrap();
+        // The bits after the first in the last byte are only flush padding.
+        for bit in 32..(stream.len() - 1) * 8 + 1 {
+            let mut corrupt = stream.clone();
+            corrupt[bit / 8] ^= 0x80 >> (bit % 8);
+            let rv = LZARIContext::new(&corrupt).strict(true).decode();
+            assert!(rv.ok().as_deref() != Some(TEXT), "{bit}");
+        }
+    }
+
+    #[test]
+    fn trailing_data() {
+        let text: Vec<u8> = (0..20000u32).map(|i| (i * i % 251) as u8).collect();
+        let mut stream = LZARIContext::new(&text).encode().unwrap();
+        let len = stream.len() as u64;
+        stream.extend(b"TRAILER!");
+
+        let mut decoder = Decoder::new(io::Cursor::new(stream));
+        let mut rv = vec![];
+        decoder.decode_to_end(&mut rv).unwrap();
+        assert_eq!(rv, text);
+        assert_eq!(decoder.consumed(), len);
+
+        let mut reader = decoder.into_inner();
+        let lookahead = u64::from(LzariParams::default().precision() + 2).div_ceil(8);
+        assert!(
+            reader.position() <= len + lookahead,
+            "{}",
+            reader.position()
+        );
+        reader.set_position(len);
+        let mut trailer = vec![];
+        reader.read_to_end(&mut trailer).unwrap();
+        assert_eq!(trailer, b"TRAILER!");
+    }
+
+    #[test]
+    fn value_outside_interval() {
+        // A consistent stream never puts `value` outside the coder's interval.
+        let mut state = DecodeState::new();
+        state.start_model();
+        state.high = state.params.q4();
+        state.value = state.high;
+        assert!(matches!(
+            state.decode_char(),
+            Err(LzariError::InvalidSymbol)
+        ));
+        assert!(matches!(
+            state.decode_position(),
+            Err(LzariError::InvalidPosition)
+        ));
+    }
+}
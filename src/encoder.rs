@@ -0,0 +1,707 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::model::Model;
+use crate::optimal::{Candidate, OptimalParser};
+use crate::{
+    CompressionLevel, HeaderFormat, LzariError, LzariParams, Match, MatchFinder, ParseStrategy,
+    Token,
+};
+
+// Positions the optimal parse looks at together. Longer blocks see further but price the
+// later tokens with staler frequencies.
+const BLOCK_LEN: usize = 1024;
+
+#[derive(Debug)]
+pub(crate) struct EncodeState {
+    pub(crate) header: HeaderFormat,
+    pub(crate) params: LzariParams,
+    pub(crate) size: Option<u64>,
+    pub(crate) parsing: ParseStrategy,
+    pub(crate) reference: bool,
+    // Where to collect the tokens as they are coded, for tools that look at the parse.
+    pub(crate) tokens: Option<Vec<Token>>,
+    started: bool,
+    count: u64,
+
+    pub(crate) outbuf: Vec<u8>,
+    out_buffer: u8,
+    out_mask: u8,
+
+    text_buf: Vec<u8>,
+    pub(crate) finder: Box<dyn MatchFinder>,
+
+    // Position in the window. `len` counts the valid bytes in the lookahead at `r`, and
+    // `advance` how many more bytes the last token covers before the next one can be coded.
+    s: usize,
+    r: usize,
+    len: usize,
+    advance: usize,
+    found_position: usize,
+    found_length: usize,
+    // A match the lazy parse has held back, starting one byte before `r`.
+    pending: Option<Match>,
+    // Positions the optimal parse has yet to code, and how many more the last match it coded
+    // covers past them.
+    block: Vec<Candidate>,
+    block_overrun: usize,
+    parser: OptimalParser,
+
+    // Arithmetic Compression
+    low: usize,
+    high: usize,
+    shifts: usize,
+    model: Model,
+}
+
+impl EncodeState {
+    pub(crate) fn new() -> Self {
+        Self {
+            header: HeaderFormat::default(),
+            params: LzariParams::default(),
+            size: None,
+            parsing: ParseStrategy::default(),
+            reference: false,
+            tokens: None,
+            started: false,
+            count: 0,
+            outbuf: vec![],
+            out_buffer: 0,
+            out_mask: 128,
+            text_buf: vec![],
+            finder: CompressionLevel::default().match_finder(),
+            s: 0,
+            r: 0,
+            len: 0,
+            advance: 0,
+            found_position: 0,
+            found_length: 0,
+            pending: None,
+            block: vec![],
+            block_overrun: 0,
+            parser: OptimalParser::new(),
+            low: 0,
+            high: 0,
+            shifts: 0,
+            model: Model::new(),
+        }
+    }
+
+    // LZARI.C writes the size as an `unsigned long`. The window is filled with spaces and the
+    // tree seeded with `insert_node(r - i)` just as the default mode does, so only an empty
+    // input comes out differently.
+    pub(crate) fn reference(&mut self) {
+        self.header = HeaderFormat::U64Le;
+        self.params = LzariParams::default();
+        self.finder = CompressionLevel::Default.match_finder();
+        self.parsing = ParseStrategy::Greedy;
+        self.reference = true;
+    }
+
+    // Gets ready for another stream, keeping the buffers and tables that were allocated for
+    // the last one.
+    pub(crate) fn reset(&mut self) {
+        self.started = false;
+        self.count = 0;
+        self.outbuf.clear();
+    }
+
+    fn put_bit(&mut self, bit: bool) {
+        if bit {
+            self.out_buffer |= self.out_mask;
+        }
+        self.out_mask >>= 1;
+        if self.out_mask == 0 {
+            self.outbuf.push(self.out_buffer);
+            self.out_buffer = 0;
+            self.out_mask = 128;
+        }
+    }
+
+    fn flush_bit_buffer(&mut self) {
+        for _ in 0..7 {
+            self.put_bit(false);
+        }
+    }
+
+    fn output(&mut self, bit: bool) {
+        self.put_bit(bit);
+        while self.shifts > 0 {
+            self.put_bit(!bit);
+            self.shifts -= 1;
+        }
+    }
+
+    fn encode_char(&mut self, ch: usize) {
+        let sym = usize::from(self.model.char_to_sym[ch]);
+        let range = self.high - self.low;
+        let total = self.model.sym_cum(0);
+        self.high = self.low + ((range * self.model.sym_cum(sym - 1)) / total);
+        self.low += (range * self.model.sym_cum(sym)) / total;
+        self.renormalize();
+        self.model.update(sym);
+    }
+
+    fn encode_position(&mut self, pos: usize) {
+        let range = self.high - self.low;
+        let total = self.model.position_cum[0] as usize;
+        self.high = self.low + ((range * self.model.position_cum[pos] as usize) / total);
+        self.low += (range * self.model.position_cum[pos + 1] as usize) / total;
+        self.renormalize();
+    }
+
+    fn renormalize(&mut self) {
+        let (q1, q2, q3) = (self.params.q1(), self.params.q2(), self.params.q3());
+        loop {
+            if self.high <= q2 {
+                self.output(false);
+            } else if self.low >= q2 {
+                self.output(true);
+                self.low -= q2;
+                self.high -= q2;
+            } else if self.low >= q1 && self.high <= q3 {
+                self.shifts += 1;
+                self.low -= q1;
+                self.high -= q1;
+            } else {
+                break;
+            }
+            self.low += self.low;
+            self.high += self.high;
+        }
+    }
+
+    fn encode_end(&mut self) {
+        self.shifts += 1;
+        if self.low < self.params.q1() {
+            self.output(false);
+        } else {
+            self.output(true);
+        }
+        self.flush_bit_buffer();
+    }
+
+    fn start(&mut self) -> Result<(), LzariError> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+
+        // Without a pledged size, leave room for the header and patch it in `finish_patched`.
+        match (self.size, self.header) {
+            (Some(size), header) => header.write(size, &mut self.outbuf)?,
+            (None, HeaderFormat::Leb128) => return Err(LzariError::MissingSize),
+            (None, header) => header.write(0, &mut self.outbuf)?,
+        }
+        if let Some(id) = self
+            .params
+            .dictionary_id()
+            .filter(|_| self.header.is_framed())
+        {
+            self.outbuf.extend(id.to_le_bytes());
+        }
+
+        let ring_buf_size = self.params.ring_buf_size();
+        let max_match_len = self.params.max_match_len();
+        self.out_buffer = 0;
+        self.out_mask = 128;
+        self.len = 0;
+        self.advance = 0;
+        self.pending = None;
+        self.block.clear();
+        self.block_overrun = 0;
+        self.low = 0;
+        self.high = self.params.q4();
+        self.shifts = 0;
+        self.model.start(&self.params);
+        self.finder.reset(&self.params);
+        self.text_buf.clear();
+        self.text_buf.resize(ring_buf_size + max_match_len - 1, 0);
+        self.s = 0;
+        self.r = ring_buf_size - max_match_len;
+        self.params.init_window(&mut self.text_buf, self.r);
+
+        // Strings that lie wholly inside the initial window can be indexed now; the last
+        // `max_match_len` run into the lookahead and wait for `prime`.
+        let window_start = self.r - self.params.initial_window().len();
+        for i in window_start..self.r - max_match_len {
+            self.finder.insert(&self.text_buf, i);
+        }
+        Ok(())
+    }
+
+    fn insert_current(&mut self) {
+        let found = self.finder.insert_and_find(&self.text_buf, self.r);
+        (self.found_position, self.found_length) =
+            found.map_or((0, 0), |found| (found.distance, found.length));
+    }
+
+    fn code_token(&mut self) {
+        if self.found_length > self.len {
+            self.found_length = self.len;
+        }
+        self.check_match();
+
+        let threshold = self.params.threshold();
+        if self.parsing == ParseStrategy::Optimal {
+            self.advance = 1;
+            if self.block_overrun > 0 {
+                self.block_overrun -= 1;
+                return;
+            }
+            self.block.push(Candidate {
+                byte: self.text_buf[self.r],
+                found: (self.found_length > threshold).then_some(Match {
+                    distance: self.found_position,
+                    length: self.found_length,
+                }),
+            });
+            if self.block.len() == BLOCK_LEN {
+                self.code_block();
+            }
+            return;
+        }
+        if self.parsing == ParseStrategy::Lazy {
+            if let Some(pending) = self.pending.take() {
+                if self.found_length <= pending.length {
+                    self.code(Token::Match(pending));
+                    self.advance = pending.length - 1;
+                    return;
+                }
+                let ring_buf_size = self.params.ring_buf_size();
+                let prev = (self.r + ring_buf_size - 1) & (ring_buf_size - 1);
+                self.code(Token::Literal(self.text_buf[prev]));
+            }
+            // Nothing can beat a match that runs to the end of the lookahead.
+            if self.found_length > threshold && self.found_length < self.len {
+                self.pending = Some(Match {
+                    distance: self.found_position,
+                    length: self.found_length,
+                });
+                self.advance = 1;
+                return;
+            }
+        }
+
+        if self.found_length <= threshold {
+            self.found_length = 1;
+            self.code(Token::Literal(self.text_buf[self.r]));
+        } else {
+            self.code(Token::Match(Match {
+                distance: self.found_position,
+                length: self.found_length,
+            }));
+        }
+        self.advance = self.found_length;
+    }
+
+    fn code_block(&mut self) {
+        self.parser
+            .parse(&self.block, &self.model, self.params.threshold());
+        let mut i = 0;
+        while i < self.block.len() {
+            let candidate = self.block[i];
+            let length = self.parser.choice[i];
+            match candidate.found {
+                Some(found) if length > 1 => self.code(Token::Match(Match {
+                    distance: found.distance,
+                    length,
+                })),
+                _ => self.code(Token::Literal(candidate.byte)),
+            }
+            i += length;
+        }
+        self.block_overrun = i - self.block.len();
+        self.block.clear();
+    }
+
+    fn code(&mut self, token: Token) {
+        if let Some(tokens) = &mut self.tokens {
+            tokens.push(token);
+        }
+        match token {
+            Token::Literal(c) => self.encode_char(c.into()),
+            Token::Match(found) => {
+                self.encode_char(255 - self.params.threshold() + found.length);
+                self.encode_position(found.distance - 1);
+            }
+        }
+    }
+
+    // Custom finders are trusted no further than the window: a match that reaches outside
+    // it, or does not match as far as claimed, is cut back to what holds.
+    fn check_match(&mut self) {
+        let ring_buf_size = self.params.ring_buf_size();
+        let max_match_len = self.params.max_match_len();
+        if self.found_position == 0 || self.found_position > ring_buf_size - max_match_len {
+            self.found_length = 0;
+            return;
+        }
+        let from = self.r.wrapping_sub(self.found_position) & (ring_buf_size - 1);
+        let length = self.found_length.min(max_match_len);
+        self.found_length = self.text_buf[self.r..self.r + length]
+            .iter()
+            .zip(&self.text_buf[from..from + length])
+            .take_while(|(a, b)| a == b)
+            .count();
+    }
+
+    // The lookahead is full (or the input has ended), so the tree can be seeded and the first
+    // token coded.
+    fn prime(&mut self) {
+        for i in 1..=self.params.max_match_len() {
+            self.finder.insert(&self.text_buf, self.r - i);
+        }
+        self.insert_current();
+        self.code_token();
+    }
+
+    fn push_byte(&mut self, c: u8) {
+        let ring_buf_size = self.params.ring_buf_size();
+        let max_match_len = self.params.max_match_len();
+        if self.len < max_match_len {
+            self.text_buf[self.r + self.len] = c;
+            self.len += 1;
+            if self.len == max_match_len {
+                self.prime();
+            }
+            return;
+        }
+
+        self.finder.remove(&self.text_buf, self.s);
+        self.text_buf[self.s] = c;
+        if self.s < max_match_len - 1 {
+            self.text_buf[self.s + ring_buf_size] = c;
+        }
+        self.s = (self.s + 1) & (ring_buf_size - 1);
+        self.r = (self.r + 1) & (ring_buf_size - 1);
+        self.insert_current();
+        self.advance -= 1;
+        if self.advance == 0 {
+            self.code_token();
+        }
+    }
+
+    pub(crate) fn encode_bytes(&mut self, buf: &[u8]) -> Result<(), LzariError> {
+        self.start()?;
+        if self
+            .size
+            .is_some_and(|size| buf.len() as u64 > size - self.count)
+        {
+            return Err(LzariError::SizeMismatch);
+        }
+
+        for &c in buf {
+            self.push_byte(c);
+        }
+        self.count += buf.len() as u64;
+        Ok(())
+    }
+
+    // Codes a parse worked out elsewhere. The tokens only have to be codable: the data they
+    // stand for is never looked at.
+    pub(crate) fn encode_tokens(&mut self, tokens: &[Token]) -> Result<(), LzariError> {
+        let threshold = self.params.threshold();
+        let max_distance = self.params.ring_buf_size() - self.params.max_match_len();
+        let valid = tokens.iter().all(|token| match token {
+            Token::Literal(_) => true,
+            Token::Match(found) => {
+                (1..=max_distance).contains(&found.distance)
+                    && (threshold + 1..=self.params.max_match_len()).contains(&found.length)
+            }
+        });
+        if !valid {
+            return Err(LzariError::InvalidToken);
+        }
+
+        self.size = Some(tokens.iter().map(|token| token.decoded_len() as u64).sum());
+        self.start()?;
+        for &token in tokens {
+            self.code(token);
+        }
+        if !(self.reference && tokens.is_empty()) {
+            self.encode_end();
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(&mut self) -> Result<(), LzariError> {
+        self.start()?;
+        if self.size.is_some_and(|size| size != self.count) {
+            return Err(LzariError::SizeMismatch);
+        }
+
+        // LZARI.C stops after the header when there is nothing to code.
+        if self.reference && self.count == 0 {
+            return Ok(());
+        }
+
+        let ring_buf_size = self.params.ring_buf_size();
+        if self.len > 0 && self.len < self.params.max_match_len() {
+            self.prime();
+        }
+        while self.len > 0 {
+            self.finder.remove(&self.text_buf, self.s);
+            self.s = (self.s + 1) & (ring_buf_size - 1);
+            self.r = (self.r + 1) & (ring_buf_size - 1);
+            self.len -= 1;
+            if self.len > 0 {
+                self.insert_current();
+            }
+            self.advance -= 1;
+            if self.advance == 0 && self.len > 0 {
+                self.code_token();
+            }
+        }
+        self.code_block();
+        self.encode_end();
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Encoder<W: Write> {
+    writer: W,
+    // Bytes handed to `writer` so far, header included.
+    written: u64,
+    // The header is written with a size of 0 and filled in by `finish_patched`.
+    patched: bool,
+    state: EncodeState,
+}
+
+impl<W: Write> Encoder<W> {
+    /// Framed headers need the size before any data is coded: pledge it with
+    /// [`size`](Self::size), or call [`patched`](Self::patched) and end with
+    /// [`finish_patched`](Self::finish_patched). Otherwise the first write fails with
+    /// [`LzariError::MissingSize`].
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            written: 0,
+            patched: false,
+            state: EncodeState::new(),
+        }
+    }
+
+    pub fn header(mut self, header: HeaderFormat) -> Self {
+        self.state.header = header;
+        self
+    }
+
+    pub fn params(mut self, params: LzariParams) -> Self {
+        self.state.params = params;
+        self
+    }
+
+    pub fn level(mut self, level: CompressionLevel) -> Self {
+        self.state.finder = level.match_finder();
+        self.state.parsing = level.parsing();
+        self
+    }
+
+    /// Replaces the match finder picked by the compression level.
+    pub fn match_finder(mut self, finder: Box<dyn MatchFinder>) -> Self {
+        self.state.finder = finder;
+        self
+    }
+
+    /// Replaces the parse strategy picked by the compression level.
+    pub fn parsing(mut self, parsing: ParseStrategy) -> Self {
+        self.state.parsing = parsing;
+        self
+    }
+
+    /// Emits exactly what Okumura's LZARI.C emits on LP64 systems: an 8-byte size header, the
+    /// default parameters and level, and nothing after the header for an empty input. Replaces
+    /// the header, parameters and level set so far.
+    pub fn reference(mut self) -> Self {
+        self.state.reference();
+        self
+    }
+
+    /// Pledges the total number of bytes that will be written, so the header can be emitted
+    /// up front.
+    pub fn size(mut self, size: u64) -> Self {
+        self.state.size = Some(size);
+        self
+    }
+
+    fn flush_outbuf(&mut self) -> Result<(), LzariError> {
+        self.writer.write_all(&self.state.outbuf)?;
+        self.written += self.state.outbuf.len() as u64;
+        self.state.outbuf.clear();
+        Ok(())
+    }
+
+    fn check_size(&self) -> Result<(), LzariError> {
+        if !self.patched
+            && self.state.size.is_none()
+            && self.state.header != HeaderFormat::Headerless
+        {
+            return Err(LzariError::MissingSize);
+        }
+        Ok(())
+    }
+
+    fn encode_bytes(&mut self, buf: &[u8]) -> Result<(), LzariError> {
+        self.check_size()?;
+        self.state.encode_bytes(buf)?;
+        if self.state.outbuf.len() >= 4096 {
+            self.flush_outbuf()?;
+        }
+        Ok(())
+    }
+
+    fn finish_stream(&mut self) -> Result<(), LzariError> {
+        self.state.finish()?;
+        self.flush_outbuf()
+    }
+
+    /// Fails with [`LzariError::MissingSize`] for a framed header without a pledged size;
+    /// such a stream has to end with [`finish_patched`](Self::finish_patched).
+    pub fn finish(mut self) -> Result<W, LzariError> {
+        if self.state.size.is_none() && self.state.header != HeaderFormat::Headerless {
+            return Err(LzariError::MissingSize);
+        }
+        self.finish_stream()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write + Seek> Encoder<W> {
+    /// Lets data be written without a pledged size. The header goes out with a size of 0 and
+    /// [`finish_patched`](Self::finish_patched) seeks back to fill it in.
+    pub fn patched(mut self) -> Self {
+        self.patched = true;
+        self
+    }
+
+    /// Finishes the stream and seeks back to fill in the header with the number of bytes that
+    /// were written.
+    pub fn finish_patched(mut self) -> Result<W, LzariError> {
+        self.finish_stream()?;
+        if self.state.size.is_none() {
+            let mut header = vec![];
+            self.state.header.write(self.state.count, &mut header)?;
+            self.writer
+                .seek(SeekFrom::Current(-(self.written as i64)))?;
+            self.writer.write_all(&header)?;
+            self.writer
+                .seek(SeekFrom::Current(self.written as i64 - header.len() as i64))?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encode_bytes(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_outbuf()?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::LZARIContext;
+
+    #[test]
+    fn missing_size_fails_before_writing() {
+        let mut encoder = Encoder::new(vec![]);
+        assert!(encoder.write_all(b"abc").is_err());
+        assert!(encoder.writer.is_empty());
+    }
+
+    #[test]
+    fn patched_header() {
+        let data = b"abcabcabcabc";
+        let mut encoder = Encoder::new(Cursor::new(vec![])).patched();
+        encoder.write_all(data).unwrap();
+        let stream = encoder.finish_patched().unwrap().into_inner();
+        assert_eq!(stream, LZARIContext::new(data).encode().unwrap());
+    }
+
+    const TEXT: &[u8] = include_bytes!("../tests/data/text.bin");
+    const BINARY: &[u8] = include_bytes!("../tests/data/binary.bin");
+
+    #[test]
+    fn optimal_parse() {
+        for data in [TEXT, BINARY, b"", b"a", b"abcabcabcabc"] {
+            let stream = LZARIContext::new(data)
+                .parsing(ParseStrategy::Optimal)
+                .encode()
+                .unwrap();
+            let decoded = LZARIContext::new(&stream).strict(true).decode().unwrap();
+            assert!(decoded == data, "{}", data.len());
+        }
+    }
+
+    #[test]
+    fn best_no_larger_than_greedy() {
+        let greedy = LZARIContext::new(TEXT).encode().unwrap();
+        let best = LZARIContext::new(TEXT)
+            .level(CompressionLevel::Best)
+            .encode()
+            .unwrap();
+        assert!(
+            best.len() <= greedy.len(),
+            "{} {}",
+            best.len(),
+            greedy.len()
+        );
+    }
+
+    // Claims a match at every position, at distances in and out of the window and far longer
+    // than anything that can be coded.
+    #[derive(Debug, Default)]
+    struct WrongFinder {
+        calls: usize,
+    }
+
+    impl MatchFinder for WrongFinder {
+        fn reset(&mut self, _params: &LzariParams) {}
+
+        fn insert(&mut self, _window: &[u8], _pos: usize) {}
+
+        fn find(&mut self, _window: &[u8], _pos: usize) -> Option<Match> {
+            const DISTANCES: [usize; 8] = [0, 1, 3, 64, 4036, 4037, 4096, usize::MAX];
+            self.calls += 1;
+            Some(Match {
+                distance: DISTANCES[self.calls % DISTANCES.len()],
+                length: usize::MAX,
+            })
+        }
+
+        fn remove(&mut self, _window: &[u8], _pos: usize) {}
+    }
+
+    #[test]
+    fn check_match() {
+        let mut data = vec![];
+        for i in 0..2000 {
+            data.extend(format!("{} {} ", i % 7, i % 100).bytes());
+        }
+        data.extend([b' '; 300]);
+        for parsing in [
+            ParseStrategy::Greedy,
+            ParseStrategy::Lazy,
+            ParseStrategy::Optimal,
+        ] {
+            let stream = LZARIContext::new(&data)
+                .match_finder(Box::new(WrongFinder::default()))
+                .parsing(parsing)
+                .encode()
+                .unwrap();
+            let decoded = LZARIContext::new(&stream).strict(true).decode().unwrap();
+            assert!(decoded == data, "{parsing:?}");
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum LzariError {
+    HeaderTooShort,
+    InvalidHeader,
+    MissingSize,
+    InputTooLarge,
+    SizeMismatch,
+    Truncated,
+    InvalidSymbol,
+    InvalidPosition,
+    SizeOverrun,
+    InvalidParams,
+    DictionaryMismatch,
+    TrailingData,
+    InvalidToken,
+    Io(io::Error),
+}
+
+impl fmt::Display for LzariError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HeaderTooShort => write!(f, "input is too short to contain a header"),
+            Self::InvalidHeader => write!(f, "header is malformed"),
+            Self::MissingSize => write!(f, "stream size was not supplied"),
+            Self::InputTooLarge => write!(f, "input is too large for the header format"),
+            Self::SizeMismatch => write!(f, "input length does not match the pledged size"),
+            Self::Truncated => write!(f, "compressed stream is truncated"),
+            Self::InvalidSymbol => write!(f, "invalid symbol in compressed stream"),
+            Self::InvalidPosition => write!(f, "invalid match position in compressed stream"),
+            Self::SizeOverrun => write!(f, "decoded data overruns the size in the header"),
+            Self::InvalidParams => write!(f, "compression parameters are out of range"),
+            Self::DictionaryMismatch => write!(f, "stream was compressed with another dictionary"),
+            Self::TrailingData => write!(f, "compressed stream is followed by other data"),
+            Self::InvalidToken => write!(f, "token cannot be coded with these parameters"),
+            Self::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Error for LzariError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LzariError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<LzariError> for io::Error {
+    fn from(e: LzariError) -> Self {
+        match e {
+            LzariError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
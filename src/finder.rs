@@ -0,0 +1,39 @@
+use std::fmt::Debug;
+
+use crate::LzariParams;
+
+/// An earlier occurrence of the string being coded, `distance` bytes back in the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    pub distance: usize,
+    pub length: usize,
+}
+
+/// Searches the window for earlier occurrences of the string at the coding position.
+///
+/// `window` is the encoder's ring buffer followed by a copy of its first
+/// `max_match_len - 1` bytes, so the string at `pos` is always
+/// `window[pos..pos + max_match_len]`. The encoder inserts every position once as the window
+/// slides and removes it again just before the byte there is overwritten. A match can reach
+/// at most `ring_buf_size - max_match_len` bytes back, as the rest of the window holds the
+/// lookahead, and only lengths above the threshold are of any use. The encoder cuts back any
+/// match that does not hold up.
+pub trait MatchFinder: Debug + Send {
+    /// Forgets every position, ready for a stream with `params`.
+    fn reset(&mut self, params: &LzariParams);
+
+    fn insert(&mut self, window: &[u8], pos: usize);
+
+    /// Returns the best match for the string at `pos` among the positions inserted so far.
+    fn find(&mut self, window: &[u8], pos: usize) -> Option<Match>;
+
+    fn remove(&mut self, window: &[u8], pos: usize);
+
+    /// What the encoder calls for each new position. Finders that search on the way to
+    /// inserting, like the binary tree, can do both at once.
+    fn insert_and_find(&mut self, window: &[u8], pos: usize) -> Option<Match> {
+        let found = self.find(window, pos);
+        self.insert(window, pos);
+        found
+    }
+}
@@ -0,0 +1,124 @@
+use crate::finder::{Match, MatchFinder};
+use crate::LzariParams;
+
+const HASH_BITS: u32 = 12;
+
+/// Chains every position to the previous one whose first bytes hash the same, and follows at
+/// most `depth` links per search. Cheaper than the tree, at the cost of missing matches further
+/// down the chain.
+#[derive(Debug)]
+pub struct HashChain {
+    depth: usize,
+    ring_buf_size: usize,
+    max_match_len: usize,
+    threshold: usize,
+    nil: u16,
+    head: Vec<u16>,
+    prev: Vec<u16>,
+}
+
+impl HashChain {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            ring_buf_size: 0,
+            max_match_len: 0,
+            threshold: 0,
+            nil: 0,
+            head: vec![],
+            prev: vec![],
+        }
+    }
+
+    fn hash(&self, key: &[u8]) -> usize {
+        let h = key[..(self.threshold + 1).min(4)]
+            .iter()
+            .fold(0u32, |h, &b| {
+                (h << 8 | u32::from(b)).wrapping_mul(0x9E37_79B1)
+            });
+        (h >> (32 - HASH_BITS)) as usize
+    }
+}
+
+impl MatchFinder for HashChain {
+    fn reset(&mut self, params: &LzariParams) {
+        self.ring_buf_size = params.ring_buf_size();
+        self.max_match_len = params.max_match_len();
+        self.threshold = params.threshold();
+        self.nil = self.ring_buf_size as u16;
+        self.head.clear();
+        self.head.resize(1 << HASH_BITS, self.nil);
+        self.prev.clear();
+        self.prev.resize(self.ring_buf_size, self.nil);
+    }
+
+    fn insert(&mut self, window: &[u8], pos: usize) {
+        let h = self.hash(&window[pos..pos + self.max_match_len]);
+        self.prev[pos] = self.head[h];
+        self.head[h] = pos as u16;
+    }
+
+    fn find(&mut self, window: &[u8], pos: usize) -> Option<Match> {
+        let key = &window[pos..pos + self.max_match_len];
+
+        // Links are never unlinked, so a chain can run into positions that have since been
+        // overwritten or lie in the lookahead. Only the distance says whether a candidate is
+        // still part of the window the decoder has.
+        let max_distance = self.ring_buf_size - self.max_match_len;
+        let mut best: Option<Match> = None;
+        let mut next = self.head[self.hash(key)];
+        for _ in 0..self.depth {
+            if next == self.nil {
+                break;
+            }
+            let candidate = usize::from(next);
+            let distance = pos.wrapping_sub(candidate) & (self.ring_buf_size - 1);
+            if distance == 0 || distance > max_distance {
+                break;
+            }
+            let other = &window[candidate..candidate + self.max_match_len];
+            let length = key.iter().zip(other).take_while(|(a, b)| a == b).count();
+            if length > self.threshold && best.is_none_or(|best| length > best.length) {
+                best = Some(Match { distance, length });
+                if length == self.max_match_len {
+                    break;
+                }
+            }
+            next = self.prev[candidate];
+        }
+        best
+    }
+
+    fn remove(&mut self, _window: &[u8], _pos: usize) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LZARIContext;
+
+    #[test]
+    fn round_trip() {
+        // Several windows' worth, so positions are removed and their slots reused, with
+        // repeats both inside the window and just out of its reach.
+        let mut data = vec![];
+        for i in 0..3000 {
+            data.extend(format!("line {} of {}; ", i % 37, i % 11).bytes());
+            if i % 200 == 199 && data.len() > 4100 {
+                let start = data.len() - 4100;
+                data.extend_from_within(start..start + 80);
+            }
+        }
+        assert!(data.len() > 4 * LzariParams::default().ring_buf_size());
+
+        for depth in [1, 4, 64] {
+            let stream = LZARIContext::new(&data)
+                .match_finder(Box::new(HashChain::new(depth)))
+                .encode()
+                .unwrap();
+            let decoded = LZARIContext::new(&stream).strict(true).decode().unwrap();
+            assert!(decoded == data, "{depth}");
+            assert!(stream.len() < data.len() / 4, "{depth}: {}", stream.len());
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use crate::LzariError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeaderFormat {
+    #[default]
+    U32Le,
+    U32Be,
+    U64Le,
+    Leb128,
+    /// The decompressed size is stored elsewhere and supplied by the caller.
+    Headerless,
+}
+
+impl HeaderFormat {
+    pub(crate) fn is_framed(self) -> bool {
+        self != Self::Headerless
+    }
+
+    pub(crate) fn write(self, size: u64, out: &mut Vec<u8>) -> Result<(), LzariError> {
+        match self {
+            Self::U32Le => {
+                let size = u32::try_from(size).map_err(|_| LzariError::InputTooLarge)?;
+                out.extend(size.to_le_bytes());
+            }
+            Self::U32Be => {
+                let size = u32::try_from(size).map_err(|_| LzariError::InputTooLarge)?;
+                out.extend(size.to_be_bytes());
+            }
+            Self::U64Le => out.extend(size.to_le_bytes()),
+            Self::Leb128 => {
+                let mut size = size;
+                while size >= 0x80 {
+                    out.push(size as u8 | 0x80);
+                    size >>= 7;
+                }
+                out.push(size as u8);
+            }
+            Self::Headerless => {}
+        }
+        Ok(())
+    }
+
+    // Returns the decompressed size and the length of the header. `size` is only consulted for
+    // headerless streams.
+    pub(crate) fn read(self, input: &[u8], size: Option<u64>) -> Result<(u64, usize), LzariError> {
+        match self {
+            Self::U32Le => {
+                let header = input.get(0..4).ok_or(LzariError::HeaderTooShort)?;
+                Ok((u32::from_le_bytes(header.try_into().unwrap()).into(), 4))
+            }
+            Self::U32Be => {
+                let header = input.get(0..4).ok_or(LzariError::HeaderTooShort)?;
+                Ok((u32::from_be_bytes(header.try_into().unwrap()).into(), 4))
+            }
+            Self::U64Le => {
+                let header = input.get(0..8).ok_or(LzariError::HeaderTooShort)?;
+                Ok((u64::from_le_bytes(header.try_into().unwrap()), 8))
+            }
+            Self::Leb128 => {
+                let mut size = 0u64;
+                for (i, &byte) in input.iter().enumerate() {
+                    let bits = u64::from(byte & 0x7F);
+                    if i * 7 >= 64 || (bits << (i * 7)) >> (i * 7) != bits {
+                        return Err(LzariError::InvalidHeader);
+                    }
+                    size |= bits << (i * 7);
+                    if byte & 0x80 == 0 {
+                        return Ok((size, i + 1));
+                    }
+                }
+                Err(LzariError::HeaderTooShort)
+            }
+            Self::Headerless => Ok((size.ok_or(LzariError::MissingSize)?, 0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::{Encoder, LZARIContext};
+
+    const TEXT: &[u8] = b"the quick brown fox jumps over the lazy dog, the quick brown fox";
+
+    fn round_trip(header: HeaderFormat) -> Vec<u8> {
+        let stream = LZARIContext::new(TEXT).header(header).encode().unwrap();
+        let (decoded, consumed) = LZARIContext::new(&stream)
+            .header(header)
+            .strict(true)
+            .decode_with_consumed()
+            .unwrap();
+        assert_eq!(decoded, TEXT);
+        assert_eq!(consumed, stream.len());
+        stream
+    }
+
+    #[test]
+    fn u64_le() {
+        let stream = round_trip(HeaderFormat::U64Le);
+        assert_eq!(stream[..8], (TEXT.len() as u64).to_le_bytes());
+    }
+
+    #[test]
+    fn leb128() {
+        let stream = round_trip(HeaderFormat::Leb128);
+        assert_eq!(stream[0], TEXT.len() as u8);
+
+        for size in [0, 0x7F, 0x80, 0x3FFF, 0x4000, u32::MAX.into(), u64::MAX] {
+            let mut header = vec![];
+            HeaderFormat::Leb128.write(size, &mut header).unwrap();
+            let rv = HeaderFormat::Leb128.read(&header, None).unwrap();
+            assert_eq!(rv, (size, header.len()), "{size}");
+        }
+    }
+
+    #[test]
+    fn u32_be() {
+        let stream = round_trip(HeaderFormat::U32Be);
+        assert_eq!(stream[..4], (TEXT.len() as u32).to_be_bytes());
+    }
+
+    #[test]
+    fn headerless() {
+        let framed = LZARIContext::new(TEXT).encode().unwrap();
+        let stream = LZARIContext::new(TEXT)
+            .header(HeaderFormat::Headerless)
+            .encode()
+            .unwrap();
+        assert_eq!(stream, framed[4..]);
+
+        // The encoder doesn't need to know the size up front when it writes no header.
+        let mut encoder = Encoder::new(vec![]).header(HeaderFormat::Headerless);
+        encoder.write_all(TEXT).unwrap();
+        assert_eq!(encoder.finish().unwrap(), stream);
+
+        let decoded = LZARIContext::new(&stream)
+            .header(HeaderFormat::Headerless)
+            .size(TEXT.len() as u64)
+            .strict(true)
+            .decode()
+            .unwrap();
+        assert_eq!(decoded, TEXT);
+
+        let rv = LZARIContext::new(&stream)
+            .header(HeaderFormat::Headerless)
+            .decode();
+        assert!(matches!(rv, Err(LzariError::MissingSize)), "{rv:?}");
+    }
+
+    #[test]
+    fn input_too_large() {
+        for header in [HeaderFormat::U32Le, HeaderFormat::U32Be] {
+            let mut encoder = Encoder::new(vec![]).header(header).size(1 << 33);
+            let e = encoder.write(b"x").unwrap_err();
+            let e = e.into_inner().unwrap().downcast::<LzariError>().unwrap();
+            assert!(matches!(*e, LzariError::InputTooLarge), "{header:?}: {e:?}");
+        }
+    }
+
+    #[test]
+    fn leb128_too_long() {
+        // Eleven bytes of continuation, and ten whose last carries bits past the 64th.
+        let rv = HeaderFormat::Leb128.read(&[0x80; 11], None);
+        assert!(matches!(rv, Err(LzariError::InvalidHeader)), "{rv:?}");
+        let mut header = [0xFF; 10];
+        header[9] = 0x02;
+        let rv = HeaderFormat::Leb128.read(&header, None);
+        assert!(matches!(rv, Err(LzariError::InvalidHeader)), "{rv:?}");
+
+        let mut stream = vec![0x80; 11];
+        stream.extend(
+            LZARIContext::new(TEXT)
+                .header(HeaderFormat::Headerless)
+                .encode()
+                .unwrap(),
+        );
+        let rv = LZARIContext::new(&stream)
+            .header(HeaderFormat::Leb128)
+            .decode();
+        assert!(matches!(rv, Err(LzariError::InvalidHeader)), "{rv:?}");
+    }
+
+    #[test]
+    fn leb128_cut_off() {
+        let rv = HeaderFormat::Leb128.read(&[0x80, 0x80], None);
+        assert!(matches!(rv, Err(LzariError::HeaderTooShort)), "{rv:?}");
+        let rv = LZARIContext::new(&[0xFF, 0xFF])
+            .header(HeaderFormat::Leb128)
+            .decode();
+        assert!(matches!(rv, Err(LzariError::HeaderTooShort)), "{rv:?}");
+    }
+}
@@ -0,0 +1,46 @@
+use crate::finder::MatchFinder;
+use crate::hash_chain::HashChain;
+use crate::tree::BinaryTree;
+
+/// Trades speed for ratio by picking the match finder and parse strategy. The output of every
+/// level decodes the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompressionLevel {
+    /// Hash-chain match finder with a short search, parsed greedily.
+    Fast,
+    /// The binary tree match finder and greedy parse from LZARI.C, byte for byte.
+    #[default]
+    Default,
+    /// The binary tree match finder with the optimal parse.
+    Best,
+}
+
+impl CompressionLevel {
+    pub(crate) fn match_finder(self) -> Box<dyn MatchFinder> {
+        match self {
+            Self::Fast => Box::new(HashChain::new(16)),
+            Self::Default | Self::Best => Box::new(BinaryTree::new()),
+        }
+    }
+
+    pub(crate) fn parsing(self) -> ParseStrategy {
+        match self {
+            Self::Fast | Self::Default => ParseStrategy::Greedy,
+            Self::Best => ParseStrategy::Optimal,
+        }
+    }
+}
+
+/// How the encoder chooses between the matches it is offered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseStrategy {
+    /// Codes the match at each position as soon as it is found, as LZARI.C does.
+    #[default]
+    Greedy,
+    /// Holds a match back for one byte and codes a literal instead if the next position has a
+    /// longer one.
+    Lazy,
+    /// Collects the matches for a block of input and codes the cheapest way through it, with
+    /// literals and matches priced by the model as it stood at the start of the block.
+    Optimal,
+}
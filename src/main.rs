@@ -1,24 +1,369 @@
 use std::env;
 use std::fs::{read, write};
 
-use lzari::LZARIContext;
+use lzari::{
+    find_variant, train_dictionary, Codec, CompressionLevel, HeaderFormat, LZARIContext,
+    LzariParams, ParseStrategy, SearchResult, Token,
+};
+
+struct Options {
+    header: HeaderFormat,
+    size: Option<u64>,
+    level: CompressionLevel,
+    parsing: Option<ParseStrategy>,
+    params: LzariParams,
+    dict_size: Option<usize>,
+    files: Vec<String>,
+}
 
 fn main() {
     let mut args = env::args();
     let prog = args.next().unwrap();
     let mode = args.next().unwrap();
-    let infile = args.next().unwrap();
-    let outfile = args.next().unwrap();
+    let options = parse_options(&prog, args);
+
+    match mode.as_str() {
+        // lzari t <dictfile> <sample>...
+        "t" | "T" => train(&prog, &options.files, &options.params, options.dict_size),
+        // lzari dump <infile>
+        "dump" => dump(
+            &prog,
+            &options.files,
+            options.header,
+            options.params,
+            options.size,
+        ),
+        // lzari diff <a> <b>
+        "diff" => diff(
+            &prog,
+            &options.files,
+            options.header,
+            options.params,
+            options.size,
+        ),
+        // lzari s <infile>
+        "s" | "S" => search(&prog, &options.files, &options.params),
+        // lzari e|d <infile> <outfile>
+        "e" | "E" | "d" | "D" => code(&prog, &mode, options),
+        _ => panic!("{prog}: invalid mode {mode}"),
+    }
+}
+
+fn parse_options(prog: &str, mut args: impl Iterator<Item = String>) -> Options {
+    let mut header = HeaderFormat::default();
+    let mut size = None;
+    let mut level = CompressionLevel::default();
+    let mut parsing = None;
+    let defaults = LzariParams::default();
+    let mut window = defaults.ring_buf_size();
+    let mut max_match = defaults.max_match_len();
+    let mut threshold = defaults.threshold();
+    let mut precision = defaults.precision();
+    let mut fill = defaults.fill();
+    let mut initial_window = None;
+    let mut dictionary = None;
+    let mut dict_size = None;
+    let mut files = vec![];
+    while let Some(opt) = args.next() {
+        if !opt.starts_with("--") {
+            files.push(opt);
+            continue;
+        }
+        let value = args
+            .next()
+            .unwrap_or_else(|| panic!("{prog}: missing value for {opt}"));
+        match opt.as_str() {
+            "--header" => {
+                header = match value.as_str() {
+                    "u32" => HeaderFormat::U32Le,
+                    "u32be" => HeaderFormat::U32Be,
+                    "u64" => HeaderFormat::U64Le,
+                    "leb128" => HeaderFormat::Leb128,
+                    "none" => HeaderFormat::Headerless,
+                    _ => panic!("{prog}: invalid header format {value}"),
+                }
+            }
+            "--level" => {
+                level = match value.as_str() {
+                    "fast" => CompressionLevel::Fast,
+                    "default" => CompressionLevel::Default,
+                    "best" => CompressionLevel::Best,
+                    _ => panic!("{prog}: invalid compression level {value}"),
+                }
+            }
+            "--parse" => {
+                parsing = Some(match value.as_str() {
+                    "greedy" => ParseStrategy::Greedy,
+                    "lazy" => ParseStrategy::Lazy,
+                    "optimal" => ParseStrategy::Optimal,
+                    _ => panic!("{prog}: invalid parse strategy {value}"),
+                })
+            }
+            "--size" => {
+                size = Some(
+                    value
+                        .parse()
+                        .unwrap_or_else(|_| panic!("{prog}: invalid size {value}")),
+                )
+            }
+            "--window" => {
+                window = value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("{prog}: invalid window size {value}"))
+            }
+            "--max-match" => {
+                max_match = value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("{prog}: invalid match length {value}"))
+            }
+            "--threshold" => {
+                threshold = value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("{prog}: invalid threshold {value}"))
+            }
+            "--precision" => {
+                precision = value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("{prog}: invalid precision {value}"))
+            }
+            "--fill" => {
+                fill = value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("{prog}: invalid fill byte {value}"))
+            }
+            "--initial-window" => initial_window = Some(read(value).unwrap()),
+            "--dict" => dictionary = Some(read(value).unwrap()),
+            "--dict-size" => {
+                dict_size = Some(
+                    value
+                        .parse()
+                        .unwrap_or_else(|_| panic!("{prog}: invalid dictionary size {value}")),
+                )
+            }
+            _ => panic!("{prog}: invalid option {opt}"),
+        }
+    }
+
+    let mut params = LzariParams::with_precision(window, max_match, threshold, precision)
+        .unwrap_or_else(|e| panic!("{prog}: {e}"))
+        .with_fill(fill);
+    if let Some(initial_window) = initial_window {
+        params = params
+            .with_initial_window(&initial_window)
+            .unwrap_or_else(|e| panic!("{prog}: {e}"));
+    }
+    if let Some(dictionary) = dictionary {
+        params = params.with_dictionary(&dictionary);
+    }
+
+    Options {
+        header,
+        size,
+        level,
+        parsing,
+        params,
+        dict_size,
+        files,
+    }
+}
+
+fn train(prog: &str, files: &[String], params: &LzariParams, dict_size: Option<usize>) {
+    let (outfile, samples) = files
+        .split_first()
+        .unwrap_or_else(|| panic!("{prog}: missing dictionary file"));
+    let samples: Vec<_> = samples.iter().map(|f| read(f).unwrap()).collect();
+    let size = dict_size.unwrap_or(params.ring_buf_size() - params.max_match_len());
+    write(outfile, train_dictionary(&samples, size)).unwrap();
+}
+
+fn dump(
+    prog: &str,
+    files: &[String],
+    header: HeaderFormat,
+    params: LzariParams,
+    size: Option<u64>,
+) {
+    let [infile] = files else {
+        panic!("{prog}: expected an input file");
+    };
+    let stream = read(infile).unwrap();
+    let mut codec = Codec::new().header(header).params(params);
+    println!(
+        "{:>10} {:>10} {:>6} {:>11} {:>6}  token",
+        "bit", "offset", "symbol", "freq", "bits"
+    );
+    let traces = match size {
+        Some(size) => codec.trace_sized_tokens(&stream, size),
+        None => codec.trace_tokens(&stream),
+    };
+    for trace in traces {
+        let trace = trace.unwrap_or_else(|e| panic!("{prog}: {e}"));
+        println!(
+            "{:>10} {:>10} {:>6} {:>11} {:>6.2}  {}",
+            trace.bit_offset,
+            trace.offset,
+            trace.symbol,
+            format!("{}/{}", trace.freq, trace.total),
+            trace.bits,
+            describe(trace.token)
+        );
+    }
+}
 
+fn diff(
+    prog: &str,
+    files: &[String],
+    header: HeaderFormat,
+    params: LzariParams,
+    size: Option<u64>,
+) {
+    let [a, b] = files else {
+        panic!("{prog}: expected two input files");
+    };
+    let streams = [read(a).unwrap(), read(b).unwrap()];
+    let mut codecs = [(); 2].map(|_| Codec::new().header(header).params(params.clone()));
+
+    let (index, divergence) = {
+        let [codec_a, codec_b] = &mut codecs;
+        let (mut traces_a, mut traces_b) = match size {
+            Some(size) => (
+                codec_a.trace_sized_tokens(&streams[0], size),
+                codec_b.trace_sized_tokens(&streams[1], size),
+            ),
+            None => (
+                codec_a.trace_tokens(&streams[0]),
+                codec_b.trace_tokens(&streams[1]),
+            ),
+        };
+        let mut index = 0;
+        loop {
+            match (traces_a.next(), traces_b.next()) {
+                (None, None) => break (index, None),
+                (Some(Ok(a)), Some(Ok(b))) if a.token == b.token => index += 1,
+                (a, b) => break (index, Some([a, b])),
+            }
+        }
+    };
+
+    // The data gives the context around the divergence, as far as it decodes.
+    let mut payloads = [vec![], vec![]];
+    let mut failed = None;
+    for (i, payload) in payloads.iter_mut().enumerate() {
+        let rv = match size {
+            Some(size) => codecs[i].decode_sized_into(&streams[i], size, payload),
+            None => codecs[i].decode_into(&streams[i], payload),
+        };
+        if let Err(e) = rv {
+            println!("{}: {e}", files[i]);
+            failed.get_or_insert(&files[i]);
+        }
+    }
+
+    match divergence {
+        None => println!("token streams are identical ({index} tokens)"),
+        Some(traces) => {
+            let mut offset = None;
+            let mut lines = vec![];
+            for (file, trace) in files.iter().zip(traces) {
+                lines.push(match trace {
+                    Some(Ok(trace)) => {
+                        offset = Some(trace.offset as usize);
+                        format!(
+                            "{file}: bit {}: {}",
+                            trace.bit_offset,
+                            describe(trace.token)
+                        )
+                    }
+                    Some(Err(e)) => format!("{file}: {e}"),
+                    None => format!("{file}: end of stream"),
+                });
+            }
+            // Both streams decode to the same data up to the divergence.
+            let offset = offset.unwrap_or(payloads[0].len().max(payloads[1].len()));
+            println!("first divergent token: #{index} at offset {offset}");
+            for line in lines {
+                println!("{line}");
+            }
+            let longer = payloads.iter().max_by_key(|payload| payload.len()).unwrap();
+            let before = &longer[offset.saturating_sub(32)..offset.min(longer.len())];
+            println!("before: \"{}\"", before.escape_ascii());
+            for (file, payload) in files.iter().zip(&payloads) {
+                match payload.get(offset..) {
+                    Some(after) => println!(
+                        "{file}: \"{}\"",
+                        after[..after.len().min(32)].escape_ascii()
+                    ),
+                    None => println!("{file}: not decoded this far"),
+                }
+            }
+        }
+    }
+
+    let [a, b] = &payloads;
+    if let Some(file) = failed {
+        println!("decompressed payloads cannot be compared: {file} does not decode");
+        return;
+    }
+    match a.iter().zip(b).position(|(a, b)| a != b) {
+        None if a.len() == b.len() => println!("decompressed payloads are identical"),
+        None => println!(
+            "decompressed payloads differ in length: {} and {} bytes",
+            a.len(),
+            b.len()
+        ),
+        Some(offset) => println!("decompressed payloads differ from offset {offset}"),
+    }
+}
+
+fn search(prog: &str, files: &[String], params: &LzariParams) {
+    let [infile] = files else {
+        panic!("{prog}: expected an input file");
+    };
+    let stream = read(infile).unwrap();
+    match find_variant(&stream, params).unwrap_or_else(|e| panic!("{prog}: {e}")) {
+        SearchResult::Found(variant) => println!("reproduced with {variant:?}"),
+        SearchResult::Diverged(divergence) => {
+            println!("no variant reproduces the stream");
+            println!("closest: {:?}", divergence.variant);
+            println!(
+                "first divergent token: #{} at offset {}",
+                divergence.token, divergence.offset
+            );
+            println!("original:   {:?}", divergence.original);
+            println!("re-encoded: {:?}", divergence.reencoded);
+        }
+    }
+}
+
+fn code(prog: &str, mode: &str, options: Options) {
+    let [infile, outfile] = &options.files[..] else {
+        panic!("{prog}: expected an input and an output file");
+    };
     let infile = read(infile).unwrap();
 
-    let lzari = LZARIContext::new(&infile);
+    let mut lzari = LZARIContext::new(&infile)
+        .header(options.header)
+        .params(options.params)
+        .level(options.level);
+    if let Some(parsing) = options.parsing {
+        lzari = lzari.parsing(parsing);
+    }
+    if let Some(size) = options.size {
+        lzari = lzari.size(size);
+    }
 
-    let out = match mode.as_str() {
+    let out = match mode {
         "e" | "E" => lzari.encode(),
-        "d" | "D" => lzari.decode(),
-        _ => panic!("{prog}: invalid mode {mode}"),
-    };
+        _ => lzari.decode(),
+    }
+    .unwrap_or_else(|e| panic!("{prog}: {e}"));
 
     write(outfile, out).unwrap();
 }
+
+fn describe(token: Token) -> String {
+    match token {
+        Token::Literal(c) => format!("literal {c:02x} '{}'", c.escape_ascii()),
+        Token::Match(found) => format!("match {} back {}", found.length, found.distance),
+    }
+}
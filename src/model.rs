@@ -0,0 +1,276 @@
+use crate::LzariParams;
+
+#[cfg(not(feature = "fenwick"))]
+type SymCum = LinearCum;
+#[cfg(feature = "fenwick")]
+type SymCum = FenwickCum;
+
+#[derive(Debug)]
+pub(crate) struct Model {
+    n_char: usize,
+    max_cum: usize,
+    // Symbols and characters number at most a few thousand, and cumulative frequencies stay
+    // under the coder's quarter range.
+    pub(crate) char_to_sym: Vec<u16>,
+    pub(crate) sym_to_char: Vec<u16>,
+    pub(crate) sym_freq: Vec<u32>,
+    sym_cum: SymCum,
+    pub(crate) position_cum: Vec<u32>,
+}
+
+impl Model {
+    pub(crate) fn new() -> Self {
+        Self {
+            n_char: 0,
+            max_cum: 0,
+            char_to_sym: vec![],
+            sym_to_char: vec![],
+            sym_freq: vec![],
+            sym_cum: SymCum::new(),
+            position_cum: vec![],
+        }
+    }
+
+    pub(crate) fn start(&mut self, params: &LzariParams) {
+        let n_char = params.n_char();
+        let ring_buf_size = params.ring_buf_size();
+        self.n_char = n_char;
+        self.max_cum = params.max_cum();
+        self.char_to_sym.resize(n_char, 0);
+        self.sym_to_char.resize(n_char + 1, 0);
+        self.sym_freq.resize(n_char + 1, 0);
+        self.position_cum.resize(ring_buf_size + 1, 0);
+
+        for sym in (1..n_char + 1).rev() {
+            let ch = sym - 1;
+            self.char_to_sym[ch] = sym as u16;
+            self.sym_to_char[sym] = ch as u16;
+            self.sym_freq[sym] = 1;
+        }
+        self.sym_freq[0] = 0;
+        self.sym_cum.rebuild(&self.sym_freq);
+
+        self.position_cum[ring_buf_size] = 0;
+        for i in (1..ring_buf_size + 1).rev() {
+            self.position_cum[i - 1] = self.position_cum[i] + LzariParams::position_freq(i) as u32;
+        }
+    }
+
+    // Total frequency of the symbols after `sym`; `sym_cum(0)` is the total of all of them.
+    pub(crate) fn sym_cum(&self, sym: usize) -> usize {
+        self.sym_cum.get(sym) as usize
+    }
+
+    pub(crate) fn update(&mut self, sym: usize) {
+        if self.sym_cum(0) >= self.max_cum {
+            for i in (1..self.n_char + 1).rev() {
+                self.sym_freq[i] = (self.sym_freq[i] + 1) >> 1;
+            }
+            self.sym_cum.rebuild(&self.sym_freq);
+        }
+        let i = {
+            let mut i = sym;
+            while self.sym_freq[i] == self.sym_freq[i - 1] {
+                i -= 1;
+            }
+            i
+        };
+        if i < sym {
+            let ch_i = self.sym_to_char[i];
+            let ch_sym = self.sym_to_char[sym];
+            self.sym_to_char[i] = ch_sym;
+            self.sym_to_char[sym] = ch_i;
+            self.char_to_sym[usize::from(ch_i)] = sym as u16;
+            self.char_to_sym[usize::from(ch_sym)] = i as u16;
+        }
+        self.sym_freq[i] += 1;
+        self.sym_cum.increment(i);
+    }
+
+    // Approximate bits to code each character in the current state.
+    pub(crate) fn char_costs(&self, costs: &mut Vec<f32>) {
+        let total = (self.sym_cum(0) as f32).log2();
+        costs.clear();
+        costs.extend(
+            self.char_to_sym
+                .iter()
+                .map(|&sym| total - (self.sym_freq[usize::from(sym)] as f32).log2()),
+        );
+    }
+
+    pub(crate) fn position_cost(&self, pos: usize) -> f32 {
+        let freq = self.position_cum[pos] - self.position_cum[pos + 1];
+        (self.position_cum[0] as f32).log2() - (freq as f32).log2()
+    }
+
+    pub(crate) fn binary_search_sym(&self, x: usize) -> usize {
+        self.sym_cum.search(x as u32)
+    }
+
+    pub(crate) fn binary_search_pos(&self, x: usize) -> usize {
+        let mut i = 1;
+        let mut j = self.position_cum.len() - 1;
+        while i < j {
+            let k = (i + j) / 2;
+            if self.position_cum[k] as usize > x {
+                i = k + 1;
+            } else {
+                j = k;
+            }
+        }
+        i - 1
+    }
+}
+
+// The table from LZARI.C: `cum[i]` holds the frequency of every symbol after `i`, so a
+// symbol's frequency going up touches every entry before it.
+#[cfg(not(feature = "fenwick"))]
+#[derive(Debug)]
+struct LinearCum {
+    cum: Vec<u32>,
+}
+
+#[cfg(not(feature = "fenwick"))]
+impl LinearCum {
+    fn new() -> Self {
+        Self { cum: vec![] }
+    }
+
+    fn rebuild(&mut self, freq: &[u32]) {
+        let n = freq.len() - 1;
+        self.cum.resize(n + 1, 0);
+        self.cum[n] = 0;
+        for i in (1..n + 1).rev() {
+            self.cum[i - 1] = self.cum[i] + freq[i];
+        }
+    }
+
+    fn get(&self, i: usize) -> u32 {
+        self.cum[i]
+    }
+
+    fn increment(&mut self, i: usize) {
+        for j in (0..i).rev() {
+            self.cum[j] += 1;
+        }
+    }
+
+    // The first symbol whose cumulative frequency is at most `x`.
+    fn search(&self, x: u32) -> usize {
+        let mut i = 1;
+        let mut j = self.cum.len() - 1;
+        while i < j {
+            let k = (i + j) / 2;
+            if self.cum[k] > x {
+                i = k + 1;
+            } else {
+                j = k;
+            }
+        }
+        i
+    }
+}
+
+// Keeps prefix sums of the frequencies in a Fenwick tree instead, so updates and lookups are
+// logarithmic. `cum[i]` is the total less the prefix up to `i`, which gives the same intervals
+// and therefore the same output as the table.
+#[cfg(feature = "fenwick")]
+#[derive(Debug)]
+struct FenwickCum {
+    tree: Vec<u32>,
+    total: u32,
+}
+
+#[cfg(feature = "fenwick")]
+impl FenwickCum {
+    fn new() -> Self {
+        Self {
+            tree: vec![],
+            total: 0,
+        }
+    }
+
+    fn rebuild(&mut self, freq: &[u32]) {
+        let n = freq.len() - 1;
+        self.tree.clear();
+        self.tree.extend_from_slice(freq);
+        for i in 1..n + 1 {
+            let parent = i + (i & i.wrapping_neg());
+            if parent <= n {
+                self.tree[parent] += self.tree[i];
+            }
+        }
+        self.total = freq[1..].iter().sum();
+    }
+
+    fn prefix(&self, mut i: usize) -> u32 {
+        let mut sum = 0;
+        while i > 0 {
+            sum += self.tree[i];
+            i &= i - 1;
+        }
+        sum
+    }
+
+    fn get(&self, i: usize) -> u32 {
+        self.total - self.prefix(i)
+    }
+
+    fn increment(&mut self, mut i: usize) {
+        self.total += 1;
+        while i < self.tree.len() {
+            self.tree[i] += 1;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    // The first symbol whose prefix reaches `total - x`, i.e. whose cumulative frequency is
+    // at most `x`.
+    fn search(&self, x: u32) -> usize {
+        let n = self.tree.len() - 1;
+        let mut target = self.total - x;
+        let mut pos = 0;
+        let mut step = n.checked_ilog2().map_or(0, |log| 1 << log);
+        while step > 0 {
+            if pos + step <= n && self.tree[pos + step] < target {
+                pos += step;
+                target -= self.tree[pos];
+            }
+            step >>= 1;
+        }
+        (pos + 1).min(n)
+    }
+}
+
+// Both symbol tables have to agree with plain sums over the frequencies, so run these with
+// and without the `fenwick` feature.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cumulative_frequencies() {
+        let params = LzariParams::default();
+        let mut model = Model::new();
+        model.start(&params);
+        // Enough updates to make the model halve its frequencies a few times.
+        for i in 0..100_000 {
+            let ch = (i * i + i / 7) % 300;
+            model.update(usize::from(model.char_to_sym[ch]));
+
+            if i % 9973 == 0 {
+                let mut cum = 0;
+                for sym in (0..model.n_char + 1).rev() {
+                    assert_eq!(model.sym_cum(sym), cum, "{i}: cum of {sym}");
+                    cum += model.sym_freq[sym] as usize;
+                }
+                for x in (0..model.sym_cum(0)).step_by(7) {
+                    let sym = (1..model.n_char + 1)
+                        .find(|&sym| model.sym_cum(sym) <= x)
+                        .unwrap();
+                    assert_eq!(model.binary_search_sym(x), sym, "{i}: search {x}");
+                }
+            }
+        }
+    }
+}
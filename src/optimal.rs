@@ -0,0 +1,60 @@
+use crate::model::Model;
+use crate::Match;
+
+// A position waiting for the optimal parse: its byte, and the longest match the finder
+// offered there. Any shorter match at the same distance is just as valid.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Candidate {
+    pub(crate) byte: u8,
+    pub(crate) found: Option<Match>,
+}
+
+#[derive(Debug)]
+pub(crate) struct OptimalParser {
+    char_costs: Vec<f32>,
+    // Bits to code the rest of the block from each position, and the length of the token
+    // that does it.
+    cost: Vec<f32>,
+    pub(crate) choice: Vec<usize>,
+}
+
+impl OptimalParser {
+    pub(crate) fn new() -> Self {
+        Self {
+            char_costs: vec![],
+            cost: vec![],
+            choice: vec![],
+        }
+    }
+
+    // Picks the cheapest run of literals and matches through `block`, leaving the length of the
+    // token starting at each position it passes through in `choice`. The last match may run
+    // past the end of the block. The symbols are priced by the model as it stands now, which
+    // drifts a little as the block is coded.
+    pub(crate) fn parse(&mut self, block: &[Candidate], model: &Model, threshold: usize) {
+        model.char_costs(&mut self.char_costs);
+        let n = block.len();
+        self.cost.clear();
+        self.cost.resize(n + 1, 0.0);
+        self.choice.clear();
+        self.choice.resize(n, 1);
+
+        for i in (0..n).rev() {
+            let candidate = &block[i];
+            self.cost[i] = self.char_costs[usize::from(candidate.byte)] + self.cost[i + 1];
+            let Some(found) = candidate.found else {
+                continue;
+            };
+            let position_cost = model.position_cost(found.distance - 1);
+            for length in threshold + 1..=found.length {
+                let cost = self.char_costs[255 - threshold + length]
+                    + position_cost
+                    + self.cost[n.min(i + length)];
+                if cost <= self.cost[i] {
+                    self.cost[i] = cost;
+                    self.choice[i] = length;
+                }
+            }
+        }
+    }
+}
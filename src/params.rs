@@ -0,0 +1,346 @@
+use crate::LzariError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LzariParams {
+    ring_buf_size: usize,
+    max_match_len: usize,
+    threshold: usize,
+    precision: u32,
+    fill: u8,
+    initial_window: Vec<u8>,
+    dictionary_id: Option<u32>,
+}
+
+impl LzariParams {
+    /// Picks the smallest precision from the usual 15 bits up that leaves every window
+    /// position a share of the coder's range; see [`with_precision`](Self::with_precision).
+    ///
+    /// Setting up the window for each stream takes time cubic in `max_match_len`: a few
+    /// milliseconds at 256, but seconds at 2048, however short the input.
+    pub fn new(
+        ring_buf_size: usize,
+        max_match_len: usize,
+        threshold: usize,
+    ) -> Result<Self, LzariError> {
+        (15..=30)
+            .find_map(|precision| {
+                Self::with_precision(ring_buf_size, max_match_len, threshold, precision).ok()
+            })
+            .ok_or(LzariError::InvalidParams)
+    }
+
+    /// Like `new`, but also sets the number of bits of the arithmetic coder's quarter range.
+    /// Windows larger than 4 KiB need more than the usual 15 to give every position a usable
+    /// share of the range.
+    pub fn with_precision(
+        ring_buf_size: usize,
+        max_match_len: usize,
+        threshold: usize,
+        precision: u32,
+    ) -> Result<Self, LzariError> {
+        let params = Self {
+            ring_buf_size,
+            max_match_len,
+            threshold,
+            precision,
+            fill: b' ',
+            initial_window: vec![],
+            dictionary_id: None,
+        };
+        params.validate()?;
+        Ok(params)
+    }
+
+    fn validate(&self) -> Result<(), LzariError> {
+        // The window is indexed with masks, and the encoder seeds the tree with the
+        // `max_match_len` strings in front of the initial lookahead.
+        if !self.ring_buf_size.is_power_of_two()
+            || self.ring_buf_size < 2 * self.max_match_len
+            || self.threshold == 0
+            || self.threshold > 255
+            || self.max_match_len <= self.threshold
+            || !(2..=30).contains(&self.precision)
+        {
+            return Err(LzariError::InvalidParams);
+        }
+
+        // Both models must fit under the coder's range, with no position left at zero
+        // frequency.
+        if self.n_char() >= self.max_cum()
+            || Self::position_freq(self.ring_buf_size) == 0
+            || self.position_total() > self.q1()
+        {
+            return Err(LzariError::InvalidParams);
+        }
+        Ok(())
+    }
+
+    /// Sets the byte the window is filled with before the first symbol. Encoder and decoder
+    /// must agree on it, as early matches may reach back into the fill.
+    pub fn with_fill(mut self, fill: u8) -> Self {
+        self.fill = fill;
+        self
+    }
+
+    /// Sets the bytes the window starts out with, ending right before the first byte of input.
+    /// Anything in front of them is filled with the fill byte.
+    pub fn with_initial_window(mut self, window: &[u8]) -> Result<Self, LzariError> {
+        if window.len() > self.ring_buf_size - self.max_match_len {
+            return Err(LzariError::InvalidParams);
+        }
+        self.initial_window = window.to_vec();
+        self.dictionary_id = None;
+        Ok(self)
+    }
+
+    /// Primes the window with a preset dictionary, so matches can refer to it from the first
+    /// byte. Only the tail that fits in front of the lookahead is used. Framed streams carry
+    /// the dictionary's Adler-32 after the header, so a decoder given the wrong one fails
+    /// instead of producing garbage.
+    pub fn with_dictionary(mut self, dictionary: &[u8]) -> Self {
+        let len = dictionary
+            .len()
+            .min(self.ring_buf_size - self.max_match_len);
+        self.initial_window = dictionary[dictionary.len() - len..].to_vec();
+        self.dictionary_id = Some(adler32(dictionary));
+        self
+    }
+
+    pub fn ring_buf_size(&self) -> usize {
+        self.ring_buf_size
+    }
+
+    pub fn max_match_len(&self) -> usize {
+        self.max_match_len
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub fn precision(&self) -> u32 {
+        self.precision
+    }
+
+    pub fn fill(&self) -> u8 {
+        self.fill
+    }
+
+    pub fn initial_window(&self) -> &[u8] {
+        &self.initial_window
+    }
+
+    pub fn dictionary_id(&self) -> Option<u32> {
+        self.dictionary_id
+    }
+
+    // Lays out the window in front of `r`, the position of the first byte of input.
+    pub(crate) fn init_window(&self, text_buf: &mut [u8], r: usize) {
+        let (fill, window) = text_buf[..r].split_at_mut(r - self.initial_window.len());
+        fill.fill(self.fill);
+        window.copy_from_slice(&self.initial_window);
+    }
+
+    pub(crate) fn n_char(&self) -> usize {
+        256 - self.threshold + self.max_match_len
+    }
+
+    pub(crate) fn position_freq(i: usize) -> usize {
+        10000 / (i + 200)
+    }
+
+    fn position_total(&self) -> usize {
+        (1..=self.ring_buf_size).map(Self::position_freq).sum()
+    }
+
+    // Arithmetic Compression
+
+    pub(crate) fn q1(&self) -> usize {
+        1 << self.precision
+    }
+
+    pub(crate) fn q2(&self) -> usize {
+        2 * self.q1()
+    }
+
+    pub(crate) fn q3(&self) -> usize {
+        3 * self.q1()
+    }
+
+    pub(crate) fn q4(&self) -> usize {
+        4 * self.q1()
+    }
+
+    pub(crate) fn max_cum(&self) -> usize {
+        self.q1() - 1
+    }
+}
+
+impl Default for LzariParams {
+    fn default() -> Self {
+        Self {
+            ring_buf_size: 4096,
+            max_match_len: 60,
+            threshold: 2,
+            precision: 15,
+            fill: b' ',
+            initial_window: vec![],
+            dictionary_id: None,
+        }
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompressionLevel, HeaderFormat, LZARIContext};
+
+    const DICTIONARY: &[u8] = b"{\"name\": \"\", \"email\": \"@example.com\", \"active\": true}";
+    const TEXT: &[u8] = b"{\"name\": \"ann\", \"email\": \"ann@example.com\", \"active\": true}";
+
+    fn encode(params: &LzariParams, header: HeaderFormat) -> Vec<u8> {
+        LZARIContext::new(TEXT)
+            .header(header)
+            .params(params.clone())
+            .encode()
+            .unwrap()
+    }
+
+    fn decode(
+        stream: &[u8],
+        params: &LzariParams,
+        header: HeaderFormat,
+    ) -> Result<Vec<u8>, LzariError> {
+        LZARIContext::new(stream)
+            .header(header)
+            .params(params.clone())
+            .size(TEXT.len() as u64)
+            .strict(true)
+            .decode()
+    }
+
+    #[test]
+    fn derived_precision() {
+        assert_eq!(
+            LzariParams::new(4096, 60, 2).unwrap(),
+            LzariParams::default()
+        );
+        assert_eq!(LzariParams::new(8192, 60, 2).unwrap().precision(), 16);
+        assert!(matches!(
+            LzariParams::with_precision(8192, 60, 2, 15),
+            Err(LzariError::InvalidParams)
+        ));
+        assert!(matches!(
+            LzariParams::new(4096, 60, 0),
+            Err(LzariError::InvalidParams)
+        ));
+    }
+
+    #[test]
+    fn window_and_match_lengths() {
+        // Copies from up to 20000 bytes back, further than the largest window reaches, in runs
+        // long enough to hit each maximum match length.
+        let mut text = vec![];
+        let mut seed = 1u32;
+        while text.len() < 40000 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            let n = (seed >> 16) as usize;
+            if n.is_multiple_of(3) && text.len() > 300 {
+                let start = text.len() - 1 - n % (text.len() - 1).min(20000);
+                let len = (seed >> 8) as usize % 300;
+                for i in 0..len {
+                    text.push(text[start + i]);
+                }
+            } else {
+                text.extend(format!("{} ", n % 1000).bytes());
+            }
+        }
+
+        for (ring_buf_size, max_match_len, threshold) in [
+            (256, 18, 2),
+            (1024, 18, 1),
+            (2048, 255, 3),
+            (4096, 60, 2),
+            (8192, 200, 3),
+        ] {
+            let params = LzariParams::new(ring_buf_size, max_match_len, threshold).unwrap();
+            for level in [CompressionLevel::Fast, CompressionLevel::Default] {
+                let stream = LZARIContext::new(&text)
+                    .params(params.clone())
+                    .level(level)
+                    .encode()
+                    .unwrap();
+                let decoded = LZARIContext::new(&stream)
+                    .params(params.clone())
+                    .strict(true)
+                    .decode()
+                    .unwrap();
+                assert!(decoded == text, "{params:?} {level:?}");
+                assert!(stream.len() < text.len() / 2, "{params:?} {level:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn dictionary() {
+        let params = LzariParams::default().with_dictionary(DICTIONARY);
+        let stream = encode(&params, HeaderFormat::U32Le);
+        assert_eq!(stream[4..8], adler32(DICTIONARY).to_le_bytes());
+        assert_eq!(decode(&stream, &params, HeaderFormat::U32Le).unwrap(), TEXT);
+
+        let plain = encode(&LzariParams::default(), HeaderFormat::U32Le);
+        assert!(
+            stream.len() < plain.len(),
+            "{} {}",
+            stream.len(),
+            plain.len()
+        );
+    }
+
+    #[test]
+    fn dictionary_mismatch() {
+        let params = LzariParams::default().with_dictionary(DICTIONARY);
+        let stream = encode(&params, HeaderFormat::U32Le);
+        let other = LzariParams::default().with_dictionary(b"some other dictionary");
+        let rv = decode(&stream, &other, HeaderFormat::U32Le);
+        assert!(matches!(rv, Err(LzariError::DictionaryMismatch)), "{rv:?}");
+        let rv = decode(&stream, &LzariParams::default(), HeaderFormat::U32Le);
+        assert!(rv.ok().as_deref() != Some(TEXT));
+    }
+
+    #[test]
+    fn headerless_dictionary() {
+        // Without a header there is nowhere to put the identifier.
+        let params = LzariParams::default().with_dictionary(DICTIONARY);
+        let framed = encode(&params, HeaderFormat::U32Le);
+        let stream = encode(&params, HeaderFormat::Headerless);
+        assert_eq!(stream, framed[8..]);
+        assert_eq!(
+            decode(&stream, &params, HeaderFormat::Headerless).unwrap(),
+            TEXT
+        );
+    }
+
+    #[test]
+    fn initial_window_drops_dictionary_id() {
+        let params = LzariParams::default()
+            .with_dictionary(DICTIONARY)
+            .with_initial_window(b"window")
+            .unwrap();
+        assert_eq!(params.dictionary_id(), None);
+        assert_eq!(params.initial_window(), b"window");
+    }
+}
@@ -0,0 +1,169 @@
+use crate::decoder::{DecodeState, Fetch, Input};
+use crate::{HeaderFormat, LzariError, LzariParams};
+
+/// What [`PushDecoder::feed`] made of the input so far. The decoder reads a few bits past the
+/// last symbol, so a stream that isn't followed by other data stays at `NeedMoreInput` until
+/// [`PushDecoder::finish`] says it has ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    NeedMoreInput,
+    Done,
+}
+
+#[derive(Debug)]
+pub(crate) struct SliceInput<'a> {
+    pub(crate) data: &'a [u8],
+    pub(crate) end: bool,
+}
+
+impl Input for SliceInput<'_> {
+    fn fetch(&mut self) -> Result<Fetch, LzariError> {
+        match self.data.split_first() {
+            Some((&byte, rest)) => {
+                self.data = rest;
+                Ok(Fetch::Byte(byte))
+            }
+            None if self.end => Ok(Fetch::End),
+            None => Ok(Fetch::Pending),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PushDecoder {
+    state: DecodeState,
+}
+
+impl PushDecoder {
+    pub fn new() -> Self {
+        Self {
+            state: DecodeState::new(),
+        }
+    }
+
+    pub fn header(mut self, header: HeaderFormat) -> Self {
+        self.state.header = header;
+        self
+    }
+
+    pub fn params(mut self, params: LzariParams) -> Self {
+        self.state.params = params;
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.state.strict = strict;
+        self
+    }
+
+    /// Reads the streams Okumura's LZARI.C writes on LP64 systems. See
+    /// [`Encoder::reference`](crate::Encoder::reference).
+    pub fn reference(mut self) -> Self {
+        self.state.header = HeaderFormat::U64Le;
+        self.state.params = LzariParams::default();
+        self.state.reference = true;
+        self
+    }
+
+    pub fn size(mut self, size: u64) -> Self {
+        self.state.size = Some(size);
+        self
+    }
+
+    /// Number of input bytes that belong to the stream, counted over every `feed` since the
+    /// start, and not counting anything the decoder had to read ahead past its end.
+    pub fn consumed(&self) -> u64 {
+        self.state.consumed()
+    }
+
+    /// Gets ready to decode another stream with the same settings, reusing the buffers
+    /// allocated for the last one.
+    pub fn reset(&mut self) {
+        self.state.reset();
+    }
+
+    /// Decodes as much as `input` allows, appending to `output`. Every byte is taken until the
+    /// stream is done; the last symbols may need the bytes after it, or a call to
+    /// [`finish`](Self::finish) if there are none. Once `Done`, [`consumed`](Self::consumed)
+    /// tells where in the input fed so far the stream ended.
+    pub fn feed(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<Status, LzariError> {
+        self.run(
+            SliceInput {
+                data: input,
+                end: false,
+            },
+            output,
+        )
+    }
+
+    /// Signals that no more input will arrive, letting the decoder pad out the last few
+    /// symbols of the stream.
+    pub fn finish(&mut self, output: &mut Vec<u8>) -> Result<Status, LzariError> {
+        self.run(
+            SliceInput {
+                data: &[],
+                end: true,
+            },
+            output,
+        )
+    }
+
+    fn run(&mut self, mut input: SliceInput, output: &mut Vec<u8>) -> Result<Status, LzariError> {
+        let mut chunk = [0; 4096];
+        loop {
+            let n = self.state.fill(&mut input, &mut chunk)?;
+            output.extend_from_slice(&chunk[..n]);
+            if n < chunk.len() {
+                break;
+            }
+        }
+
+        if self.state.is_done() {
+            Ok(Status::Done)
+        } else {
+            Ok(Status::NeedMoreInput)
+        }
+    }
+}
+
+impl Default for PushDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LZARIContext;
+
+    #[test]
+    fn byte_at_a_time() {
+        let data = b"she sells sea shells by the sea shore, the shells she sells are sea shells";
+        let stream = LZARIContext::new(data).encode().unwrap();
+        let mut decoder = PushDecoder::new();
+        let mut out = vec![];
+        for byte in &stream {
+            assert_eq!(
+                decoder.feed(&[*byte], &mut out).unwrap(),
+                Status::NeedMoreInput
+            );
+        }
+        assert_eq!(decoder.finish(&mut out).unwrap(), Status::Done);
+        assert_eq!(out, LZARIContext::new(&stream).decode().unwrap());
+        assert_eq!(decoder.consumed(), stream.len() as u64);
+    }
+
+    #[test]
+    fn done_before_trailing_data() {
+        let data = b"abcabcabcabc";
+        let mut stream = LZARIContext::new(data).encode().unwrap();
+        let len = stream.len();
+        stream.extend([0; 8]);
+        let mut decoder = PushDecoder::new();
+        let mut out = vec![];
+        assert_eq!(decoder.feed(&stream, &mut out).unwrap(), Status::Done);
+        assert_eq!(out, data);
+        assert_eq!(decoder.consumed(), len as u64);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::decoder::DecodeState;
+use crate::tree::{BinaryTree, TieBreak};
+use crate::{Codec, HeaderFormat, LzariError, LzariParams, ParseStrategy, Token};
+
+const HEADERS: [HeaderFormat; 4] = [
+    HeaderFormat::U32Le,
+    HeaderFormat::U64Le,
+    HeaderFormat::U32Be,
+    HeaderFormat::Leb128,
+];
+const FILLS: [u8; 2] = [b' ', 0];
+const TIE_BREAKS: [TieBreak; 2] = [TieBreak::Nearest, TieBreak::First];
+const PARSINGS: [ParseStrategy; 2] = [ParseStrategy::Greedy, ParseStrategy::Lazy];
+
+/// One of the encoder configurations that [`find_variant`] tries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    pub header: HeaderFormat,
+    pub fill: u8,
+    pub tie_break: TieBreak,
+    pub parsing: ParseStrategy,
+    /// Okumura's LZARI.C, which writes nothing after the header of an empty stream. It always
+    /// has the default parameters.
+    pub reference: bool,
+}
+
+impl Variant {
+    // What LZARI.C does on LP64 systems.
+    fn reference() -> Self {
+        Self {
+            header: HeaderFormat::U64Le,
+            fill: b' ',
+            tie_break: TieBreak::Nearest,
+            parsing: ParseStrategy::Greedy,
+            reference: true,
+        }
+    }
+
+    /// A codec that encodes this way, taking every other parameter from `params`.
+    pub fn codec(&self, params: &LzariParams) -> Codec {
+        if self.reference {
+            return Codec::new().reference();
+        }
+        Codec::new()
+            .header(self.header)
+            .params(params.clone().with_fill(self.fill))
+            .match_finder(Box::new(BinaryTree::new().tie_break(self.tie_break)))
+            .parsing(self.parsing)
+    }
+}
+
+/// Where the variant that came closest stops reproducing the original stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub variant: Variant,
+    /// Index of the first token that differs, and the offset in the data where it starts.
+    pub token: usize,
+    pub offset: u64,
+    /// The tokens at that point, or `None` where a stream has run out of them.
+    pub original: Option<Token>,
+    pub reencoded: Option<Token>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchResult {
+    Found(Variant),
+    Diverged(Divergence),
+}
+
+/// Looks for the encoder configuration that turns the data in `stream` back into `stream`,
+/// trying each header width, fill byte, tie-break and parse strategy with the window sizes in
+/// `params`, and then LZARI.C itself. Fails with the decoding error if no variant can decode
+/// `stream` at all.
+pub fn find_variant(stream: &[u8], params: &LzariParams) -> Result<SearchResult, LzariError> {
+    let mut fills = vec![params.fill()];
+    fills.extend(FILLS.into_iter().filter(|&fill| fill != params.fill()));
+
+    let mut error = None;
+    let mut closest: Option<(usize, Variant, Vec<u8>, Vec<Token>)> = None;
+    let mut encoded = vec![];
+    for header in HEADERS {
+        for &fill in &fills {
+            let params = params.clone().with_fill(fill);
+            let (data, tokens) = match decode_tokens(stream, header, &params, false) {
+                Ok((data, tokens, consumed)) if consumed == stream.len() => (data, tokens),
+                // The stream ends somewhere else with this header.
+                Ok(_) => continue,
+                Err(e) => {
+                    error.get_or_insert(e);
+                    continue;
+                }
+            };
+
+            for tie_break in TIE_BREAKS {
+                for parsing in PARSINGS {
+                    let variant = Variant {
+                        header,
+                        fill,
+                        tie_break,
+                        parsing,
+                        reference: false,
+                    };
+                    encoded.clear();
+                    variant.codec(&params).encode_into(&data, &mut encoded)?;
+                    if encoded == stream {
+                        return Ok(SearchResult::Found(variant));
+                    }
+
+                    let common = encoded
+                        .iter()
+                        .zip(stream)
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                    if closest.as_ref().is_none_or(|closest| common > closest.0) {
+                        closest = Some((common, variant, encoded.clone(), tokens.clone()));
+                    }
+                }
+            }
+        }
+    }
+
+    // Only an empty stream tells LZARI.C apart from the U64Le variants.
+    let reference = Variant::reference();
+    let params = LzariParams::default();
+    if let Ok((data, _, consumed)) = decode_tokens(stream, reference.header, &params, true) {
+        if consumed == stream.len() {
+            encoded.clear();
+            reference.codec(&params).encode_into(&data, &mut encoded)?;
+            if encoded == stream {
+                return Ok(SearchResult::Found(reference));
+            }
+        }
+    }
+
+    let Some((_, variant, encoded, original)) = closest else {
+        return Err(error.unwrap_or(LzariError::TrailingData));
+    };
+    let params = params.clone().with_fill(variant.fill);
+    let (_, reencoded, _) = decode_tokens(&encoded, variant.header, &params, false)?;
+    let token = original
+        .iter()
+        .zip(&reencoded)
+        .take_while(|(a, b)| a == b)
+        .count();
+    Ok(SearchResult::Diverged(Divergence {
+        token,
+        offset: original[..token]
+            .iter()
+            .map(|token| token.decoded_len() as u64)
+            .sum(),
+        original: original.get(token).copied(),
+        reencoded: reencoded.get(token).copied(),
+        variant,
+    }))
+}
+
+// The data and tokens at the start of `stream`, and the length of the stream they came from.
+fn decode_tokens(
+    stream: &[u8],
+    header: HeaderFormat,
+    params: &LzariParams,
+    reference: bool,
+) -> Result<(Vec<u8>, Vec<Token>, usize), LzariError> {
+    let mut decoder = DecodeState::new();
+    decoder.header = header;
+    decoder.params = params.clone();
+    decoder.strict = true;
+    decoder.reference = reference;
+    decoder.tokens = Some(vec![]);
+    let mut data = vec![];
+    let consumed = decoder.decode_slice(stream, &mut data)?;
+    let tokens = decoder.tokens.unwrap_or_default();
+    Ok((
+        data,
+        tokens.iter().map(|trace| trace.token).collect(),
+        consumed,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompressionLevel, LZARIContext};
+
+    const TEXT: &[u8] = b"It was the best of times, it was the worst of times, it was the age of \
+        wisdom, it was the age of foolishness, it was the epoch of belief, it was the epoch of \
+        incredulity, it was the season of Light, it was the season of Darkness";
+
+    #[test]
+    fn reference_empty_stream() {
+        let stream = LZARIContext::new(b"").reference().encode().unwrap();
+        let found = find_variant(&stream, &LzariParams::default()).unwrap();
+        assert_eq!(found, SearchResult::Found(Variant::reference()));
+    }
+
+    #[test]
+    fn finds_variant() {
+        let params = LzariParams::default();
+        let variant = Variant {
+            header: HeaderFormat::U32Be,
+            fill: 0,
+            tie_break: TieBreak::First,
+            parsing: ParseStrategy::Lazy,
+            reference: false,
+        };
+        // Starts with a match into the zero fill that would read as spaces with the default
+        // fill, and has a match the lazy parse puts off.
+        let mut data = vec![0; 8];
+        data.extend(b"    ");
+        data.extend(TEXT);
+        data.extend(b" xabcy bcdefghij abcdefghij");
+        let mut stream = vec![];
+        variant
+            .codec(&params)
+            .encode_into(&data, &mut stream)
+            .unwrap();
+        let found = find_variant(&stream, &params).unwrap();
+        assert_eq!(found, SearchResult::Found(variant));
+    }
+
+    #[test]
+    fn fast_level_diverges() {
+        let stream = LZARIContext::new(TEXT)
+            .level(CompressionLevel::Fast)
+            .encode()
+            .unwrap();
+        let found = find_variant(&stream, &LzariParams::default()).unwrap();
+        assert!(matches!(found, SearchResult::Diverged(_)), "{found:?}");
+    }
+}
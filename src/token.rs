@@ -0,0 +1,123 @@
+use crate::decoder::DecodeState;
+use crate::push::SliceInput;
+use crate::{LzariError, Match};
+
+/// One step of a parse: a byte coded as itself, or a copy of `length` bytes from `distance`
+/// bytes back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Literal(u8),
+    Match(Match),
+}
+
+impl Token {
+    /// Number of bytes the token stands for.
+    pub fn decoded_len(&self) -> usize {
+        match self {
+            Self::Literal(_) => 1,
+            Self::Match(found) => found.length,
+        }
+    }
+}
+
+/// A decoded token and what it cost, for looking into a stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenTrace {
+    pub token: Token,
+    /// Bits of the stream, header included, that the coder had settled before the token.
+    pub bit_offset: u64,
+    /// Where the token's data starts in the decoded output.
+    pub offset: u64,
+    /// The model's symbol, its rank in the frequency-sorted table, which moves as
+    /// frequencies change.
+    pub symbol: usize,
+    /// The character the symbol stood for: a byte for literals, `256 - threshold + length`
+    /// for matches.
+    pub character: usize,
+    /// The symbol's frequency and the total of all of them, before the model was updated.
+    pub freq: u32,
+    pub total: u32,
+    /// Information content of the symbol and, for matches, the position.
+    pub bits: f64,
+}
+
+/// The traced tokens of a compressed stream, in order. Returned by
+/// [`Codec::trace_tokens`](crate::Codec::trace_tokens).
+#[derive(Debug)]
+pub struct Trace<'a> {
+    decoder: &'a mut DecodeState,
+    input: SliceInput<'a>,
+    next: usize,
+    done: bool,
+}
+
+impl<'a> Trace<'a> {
+    pub(crate) fn new(decoder: &'a mut DecodeState, stream: &'a [u8]) -> Self {
+        decoder.reset();
+        decoder.tokens = Some(vec![]);
+        Self {
+            decoder,
+            input: SliceInput {
+                data: stream,
+                end: true,
+            },
+            next: 0,
+            done: false,
+        }
+    }
+
+    /// Number of input bytes that belong to the stream, once the iterator has run out.
+    pub fn consumed(&self) -> u64 {
+        self.decoder.consumed()
+    }
+}
+
+impl Iterator for Trace<'_> {
+    type Item = Result<TokenTrace, LzariError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let tokens = self.decoder.tokens.as_mut()?;
+            if let Some(&trace) = tokens.get(self.next) {
+                self.next += 1;
+                return Some(Ok(trace));
+            }
+            if self.done {
+                return None;
+            }
+            tokens.clear();
+            self.next = 0;
+
+            // The data is not kept, so any buffer will do; a short one stops soon after each
+            // token.
+            let mut chunk = [0; 64];
+            match self.decoder.fill(&mut self.input, &mut chunk) {
+                Ok(0) => self.done = true,
+                Ok(_) => {}
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Trace<'_> {
+    fn drop(&mut self) {
+        self.decoder.tokens = None;
+    }
+}
+
+/// The tokens of a compressed stream, in order. Returned by
+/// [`Codec::decode_tokens`](crate::Codec::decode_tokens).
+#[derive(Debug)]
+pub struct Tokens<'a>(pub(crate) Trace<'a>);
+
+impl Iterator for Tokens<'_> {
+    type Item = Result<Token, LzariError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|trace| trace.map(|trace| trace.token))
+    }
+}
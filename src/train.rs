@@ -0,0 +1,112 @@
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+const DMER_LEN: usize = 6;
+const SEGMENT_LEN: usize = 48;
+
+/// Builds a preset dictionary of at most `size` bytes out of the segments of `samples` that
+/// share the most substrings with the other samples. The most useful segments end up last,
+/// closest to the data, where matches are cheapest to code.
+///
+/// Only substrings found in at least two samples count, so a single sample, or samples with
+/// nothing in common, give an empty dictionary.
+pub fn train_dictionary<S: AsRef<[u8]>>(samples: &[S], size: usize) -> Vec<u8> {
+    // How many samples each substring occurs in. Repeats within a single sample are left to
+    // the window itself.
+    let mut counts = HashMap::<&[u8], usize>::new();
+    for sample in samples {
+        let dmers: HashSet<_> = sample.as_ref().windows(DMER_LEN).collect();
+        for dmer in dmers {
+            *counts.entry(dmer).or_default() += 1;
+        }
+    }
+    counts.retain(|_, count| *count > 1);
+
+    let score = |counts: &HashMap<&[u8], usize>, segment: &[u8]| -> usize {
+        let dmers: HashSet<_> = segment.windows(DMER_LEN).collect();
+        dmers
+            .into_iter()
+            .map(|dmer| counts.get(dmer).copied().unwrap_or(0))
+            .sum()
+    };
+
+    // Segments overlap by half so that runs crossing a boundary are still seen whole.
+    let mut heap = BinaryHeap::new();
+    for sample in samples {
+        let sample = sample.as_ref();
+        for start in (0..sample.len()).step_by(SEGMENT_LEN / 2) {
+            let segment = &sample[start..sample.len().min(start + SEGMENT_LEN)];
+            heap.push((score(&counts, segment), segment));
+        }
+    }
+
+    // Scores only ever go down as substrings get covered, so a segment whose score is still
+    // current when it reaches the top is the best one left.
+    let mut chosen = vec![];
+    let mut len = 0;
+    while len < size {
+        let Some((old, segment)) = heap.pop() else {
+            break;
+        };
+        if old == 0 {
+            break;
+        }
+        let new = score(&counts, segment);
+        if new < old {
+            heap.push((new, segment));
+            continue;
+        }
+        for dmer in segment.windows(DMER_LEN) {
+            counts.remove(dmer);
+        }
+        chosen.push(segment);
+        len += segment.len();
+    }
+
+    let mut dictionary: Vec<u8> = chosen.into_iter().rev().flatten().copied().collect();
+    dictionary.drain(..dictionary.len().saturating_sub(size));
+    dictionary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LZARIContext, LzariParams};
+
+    fn record(i: usize) -> Vec<u8> {
+        format!(
+            "{{\"id\": {i}, \"user\": \"user{}\", \"status\": \"{}\", \"tags\": [\"alpha\", \"beta\"]}}",
+            i * 7919 % 1000,
+            ["active", "suspended", "pending"][i % 3]
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn single_sample() {
+        assert!(train_dictionary(&[record(0)], 1024).is_empty());
+    }
+
+    #[test]
+    fn shrinks_small_records() {
+        let samples: Vec<_> = (0..50).map(record).collect();
+        let dictionary = train_dictionary(&samples, 1024);
+        assert!(!dictionary.is_empty() && dictionary.len() <= 1024);
+
+        let params = LzariParams::default().with_dictionary(&dictionary);
+        let (mut plain, mut primed) = (0, 0);
+        for record in (100..120).map(record) {
+            plain += LZARIContext::new(&record).encode().unwrap().len();
+            let stream = LZARIContext::new(&record)
+                .params(params.clone())
+                .encode()
+                .unwrap();
+            let decoded = LZARIContext::new(&stream)
+                .params(params.clone())
+                .decode()
+                .unwrap();
+            assert_eq!(decoded, record);
+            primed += stream.len();
+        }
+        assert!(primed * 2 < plain, "{primed} {plain}");
+    }
+}
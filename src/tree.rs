@@ -0,0 +1,255 @@
+use std::cmp::Ordering;
+
+use crate::finder::{Match, MatchFinder};
+use crate::LzariParams;
+
+/// Which of several equally long matches the tree settles on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TieBreak {
+    /// The closest one, as in LZARI.C.
+    #[default]
+    Nearest,
+    /// The first one met on the way down the tree, as in LZSS.C.
+    First,
+}
+
+/// Okumura's binary search tree over the strings in the window. Finds the longest match, and
+/// the closest one among equally long matches unless told otherwise.
+#[derive(Debug)]
+pub struct BinaryTree {
+    tie_break: TieBreak,
+    ring_buf_size: usize,
+    max_match_len: usize,
+    threshold: usize,
+    // Tree nodes are window positions; `nil` is one past the window, and `rson` carries the
+    // 256 roots after it. Windows are capped well below 64 KiB, so every index fits a `u16`.
+    nil: u16,
+    lson: Vec<u16>,
+    rson: Vec<u16>,
+    dad: Vec<u16>,
+    match_position: usize,
+}
+
+impl BinaryTree {
+    pub fn new() -> Self {
+        Self {
+            tie_break: TieBreak::default(),
+            ring_buf_size: 0,
+            max_match_len: 0,
+            threshold: 0,
+            nil: 0,
+            lson: vec![],
+            rson: vec![],
+            dad: vec![],
+            match_position: 0,
+        }
+    }
+
+    pub fn tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.tie_break = tie_break;
+        self
+    }
+
+    fn found(&self, length: usize) -> Option<Match> {
+        (length > 0).then_some(Match {
+            distance: self.match_position,
+            length,
+        })
+    }
+}
+
+impl Default for BinaryTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MatchFinder for BinaryTree {
+    fn reset(&mut self, params: &LzariParams) {
+        let ring_buf_size = params.ring_buf_size();
+        self.ring_buf_size = ring_buf_size;
+        self.max_match_len = params.max_match_len();
+        self.threshold = params.threshold();
+        self.nil = ring_buf_size as u16;
+        self.lson.resize(ring_buf_size + 1, 0);
+        self.rson.resize(ring_buf_size + 256 + 1, 0);
+        self.dad.resize(ring_buf_size + 1, 0);
+
+        for i in ring_buf_size + 1..ring_buf_size + 256 + 1 {
+            self.rson[i] = self.nil
+        }
+
+        for i in 0..ring_buf_size {
+            self.dad[i] = self.nil
+        }
+    }
+
+    fn insert(&mut self, window: &[u8], pos: usize) {
+        self.insert_and_find(window, pos);
+    }
+
+    // Walks the tree the way `insert_and_find` does, without linking anything in.
+    fn find(&mut self, window: &[u8], buf_pos: usize) -> Option<Match> {
+        let ring_buf_size = self.ring_buf_size;
+        let max_match_len = self.max_match_len;
+        let key = &window[buf_pos..buf_pos + max_match_len];
+        let mut pos = ring_buf_size + 1 + usize::from(key[0]);
+        let mut cmp = Ordering::Greater;
+        let mut best: Option<Match> = None;
+
+        loop {
+            let next = match cmp {
+                Ordering::Greater => self.rson[pos],
+                _ => self.lson[pos],
+            };
+            if next == self.nil {
+                return best;
+            }
+            pos = next.into();
+
+            let mut idx = 1;
+            while idx < max_match_len {
+                cmp = key[idx].cmp(&window[pos + idx]);
+                if cmp != Ordering::Equal {
+                    break;
+                }
+                idx += 1;
+            }
+
+            if idx > self.threshold {
+                let distance = buf_pos.wrapping_sub(pos) & (ring_buf_size - 1);
+                let better = best.is_none_or(|best| {
+                    idx > best.length
+                        || (idx == best.length
+                            && self.tie_break == TieBreak::Nearest
+                            && distance < best.distance)
+                });
+                if better {
+                    best = Some(Match {
+                        distance,
+                        length: idx,
+                    });
+                }
+                if idx >= max_match_len {
+                    return best;
+                }
+            }
+        }
+    }
+
+    fn insert_and_find(&mut self, window: &[u8], buf_pos: usize) -> Option<Match> {
+        let ring_buf_size = self.ring_buf_size;
+        let max_match_len = self.max_match_len;
+        let mut cmp = Ordering::Greater;
+        let key = &window[buf_pos..buf_pos + max_match_len];
+        let mut pos = ring_buf_size + 1 + usize::from(key[0]);
+        let node = buf_pos as u16;
+
+        self.rson[buf_pos] = self.nil;
+        self.lson[buf_pos] = self.nil;
+
+        let mut match_length = 0;
+
+        loop {
+            match cmp {
+                Ordering::Greater => {
+                    if self.rson[pos] != self.nil {
+                        pos = self.rson[pos].into();
+                    } else {
+                        self.rson[pos] = node;
+                        self.dad[buf_pos] = pos as u16;
+                        return self.found(match_length);
+                    }
+                }
+                _ => {
+                    if self.lson[pos] != self.nil {
+                        pos = self.lson[pos].into();
+                    } else {
+                        self.lson[pos] = node;
+                        self.dad[buf_pos] = pos as u16;
+                        return self.found(match_length);
+                    }
+                }
+            }
+
+            let idx = {
+                let mut i = 1;
+                while i < max_match_len {
+                    cmp = key[i].cmp(&window[pos + i]);
+                    if cmp != Ordering::Equal {
+                        break;
+                    }
+                    i += 1;
+                }
+                i
+            };
+
+            if idx > self.threshold {
+                match idx.cmp(&match_length) {
+                    Ordering::Greater => {
+                        self.match_position = buf_pos.wrapping_sub(pos) & (ring_buf_size - 1);
+                        match_length = idx;
+                        if idx >= max_match_len {
+                            break;
+                        }
+                    }
+                    Ordering::Equal if self.tie_break == TieBreak::Nearest => {
+                        let temp = buf_pos.wrapping_sub(pos) & (ring_buf_size - 1);
+                        if temp < self.match_position {
+                            self.match_position = temp;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let (lson, rson, dad) = (self.lson[pos], self.rson[pos], self.dad[pos]);
+        self.dad[buf_pos] = dad;
+        self.lson[buf_pos] = lson;
+        self.rson[buf_pos] = rson;
+        self.dad[usize::from(lson)] = node;
+        self.dad[usize::from(rson)] = node;
+        if usize::from(self.rson[usize::from(dad)]) == pos {
+            self.rson[usize::from(dad)] = node;
+        } else {
+            self.lson[usize::from(dad)] = node;
+        }
+        self.dad[pos] = self.nil;
+
+        self.found(match_length)
+    }
+
+    fn remove(&mut self, _window: &[u8], pos: usize) {
+        if self.dad[pos] == self.nil {
+            return;
+        }
+        let q = if self.rson[pos] == self.nil {
+            self.lson[pos]
+        } else if self.lson[pos] == self.nil {
+            self.rson[pos]
+        } else {
+            let mut q = self.lson[pos];
+            if self.rson[usize::from(q)] != self.nil {
+                while self.rson[usize::from(q)] != self.nil {
+                    q = self.rson[usize::from(q)];
+                }
+                let (lson_q, dad_q) = (self.lson[usize::from(q)], self.dad[usize::from(q)]);
+                self.rson[usize::from(dad_q)] = lson_q;
+                self.dad[usize::from(lson_q)] = dad_q;
+                self.lson[usize::from(q)] = self.lson[pos];
+                self.dad[usize::from(self.lson[pos])] = q;
+            }
+            self.rson[usize::from(q)] = self.rson[pos];
+            self.dad[usize::from(self.rson[pos])] = q;
+            q
+        };
+        let dad = self.dad[pos];
+        self.dad[usize::from(q)] = dad;
+        if usize::from(self.rson[usize::from(dad)]) == pos {
+            self.rson[usize::from(dad)] = q;
+        } else {
+            self.lson[usize::from(dad)] = q;
+        }
+        self.dad[pos] = self.nil;
+    }
+}